@@ -0,0 +1,20 @@
+CREATE TABLE events (
+  id UUID PRIMARY KEY,
+  name VARCHAR(255) NOT NULL,
+  venue VARCHAR(255) NOT NULL,
+  starts_at TIMESTAMPTZ NOT NULL,
+  capacity INT NOT NULL CHECK (capacity >= 0),
+  price INT NOT NULL CHECK (price >= 0),
+  sales_start TIMESTAMPTZ,
+  sales_end TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- orders placed before events existed all belong to the same show
+INSERT INTO events (id, name, venue, starts_at, capacity, price)
+SELECT '00000000-0000-0000-0000-000000000001', 'STHLM VISION', 'Södra Latin', '2024-03-01 19:00:00+01', 266, 115
+WHERE EXISTS (SELECT 1 FROM orders);
+
+ALTER TABLE orders ADD COLUMN event_id UUID REFERENCES events(id);
+UPDATE orders SET event_id = '00000000-0000-0000-0000-000000000001';
+ALTER TABLE orders ALTER COLUMN event_id SET NOT NULL;
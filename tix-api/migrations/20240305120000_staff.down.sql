@@ -0,0 +1,2 @@
+DROP TABLE staff;
+DROP TYPE staff_role;
@@ -0,0 +1,9 @@
+CREATE TYPE staff_role AS ENUM ('admin', 'scanner', 'viewer');
+
+CREATE TABLE staff (
+  email VARCHAR(255) PRIMARY KEY,
+  role staff_role NOT NULL,
+  name VARCHAR(255),
+  invited_by VARCHAR(255),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
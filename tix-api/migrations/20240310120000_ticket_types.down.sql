@@ -0,0 +1,5 @@
+ALTER TABLE events ADD COLUMN price INT NOT NULL DEFAULT 0 CHECK (price >= 0);
+UPDATE events e SET price = COALESCE((SELECT MIN(price) FROM ticket_types tt WHERE tt.event_id = e.id AND tt.public), 0);
+ALTER TABLE events ALTER COLUMN price DROP DEFAULT;
+ALTER TABLE tickets DROP COLUMN ticket_type_id;
+DROP TABLE ticket_types;
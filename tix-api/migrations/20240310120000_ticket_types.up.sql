@@ -0,0 +1,21 @@
+CREATE TABLE ticket_types (
+  id UUID PRIMARY KEY,
+  event_id UUID NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+  name VARCHAR(255) NOT NULL,
+  price INT NOT NULL CHECK (price >= 0),
+  quota INT NOT NULL CHECK (quota >= 0),
+  public BOOLEAN NOT NULL DEFAULT TRUE,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- every existing event gets a single type carrying its old price
+INSERT INTO ticket_types (id, event_id, name, price, quota)
+SELECT gen_random_uuid(), id, 'Standard', price, capacity FROM events;
+
+ALTER TABLE tickets ADD COLUMN ticket_type_id UUID REFERENCES ticket_types(id);
+UPDATE tickets t SET ticket_type_id = tt.id
+FROM orders o, ticket_types tt
+WHERE o.id = t.order_id AND tt.event_id = o.event_id;
+ALTER TABLE tickets ALTER COLUMN ticket_type_id SET NOT NULL;
+
+ALTER TABLE events DROP COLUMN price;
@@ -0,0 +1,18 @@
+CREATE TYPE outbox_status AS ENUM ('pending', 'sent', 'dead');
+
+CREATE TABLE outbox (
+  id UUID PRIMARY KEY,
+  kind VARCHAR(255) NOT NULL,
+  order_id TEXT REFERENCES orders(id),
+  envelope_from VARCHAR(255),
+  envelope_to VARCHAR(255)[] NOT NULL,
+  message BYTEA NOT NULL,
+  status outbox_status NOT NULL DEFAULT 'pending',
+  attempts INT NOT NULL DEFAULT 0,
+  last_error TEXT,
+  next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  sent_at TIMESTAMPTZ
+);
+
+CREATE INDEX outbox_pending_idx ON outbox (next_attempt_at) WHERE status = 'pending';
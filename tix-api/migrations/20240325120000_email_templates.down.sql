@@ -0,0 +1,2 @@
+DROP TABLE email_templates;
+DROP TYPE email_kind;
@@ -0,0 +1,11 @@
+CREATE TYPE email_kind AS ENUM ('order_confirmation', 'payment_reminder', 'tickets');
+
+CREATE TABLE email_templates (
+  event_id UUID NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+  kind email_kind NOT NULL,
+  subject TEXT NOT NULL,
+  text TEXT NOT NULL,
+  html TEXT,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (event_id, kind)
+);
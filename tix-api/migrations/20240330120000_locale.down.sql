@@ -0,0 +1,6 @@
+DELETE FROM email_templates WHERE locale <> 'sv';
+ALTER TABLE email_templates DROP CONSTRAINT email_templates_pkey;
+ALTER TABLE email_templates DROP COLUMN locale;
+ALTER TABLE email_templates ADD PRIMARY KEY (event_id, kind);
+
+ALTER TABLE orders DROP COLUMN locale;
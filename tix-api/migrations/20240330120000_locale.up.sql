@@ -0,0 +1,5 @@
+ALTER TABLE orders ADD COLUMN locale TEXT NOT NULL DEFAULT 'sv' CHECK (locale IN ('sv', 'en'));
+
+ALTER TABLE email_templates ADD COLUMN locale TEXT NOT NULL DEFAULT 'sv' CHECK (locale IN ('sv', 'en'));
+ALTER TABLE email_templates DROP CONSTRAINT email_templates_pkey;
+ALTER TABLE email_templates ADD PRIMARY KEY (event_id, kind, locale);
@@ -0,0 +1,3 @@
+DROP TABLE payments;
+DROP TABLE payment_imports;
+DROP TYPE payment_status;
@@ -0,0 +1,30 @@
+CREATE TYPE payment_status AS ENUM ('matched', 'amount_mismatch', 'unknown_order', 'duplicate', 'already_paid');
+
+CREATE TABLE payment_imports (
+  id UUID PRIMARY KEY,
+  filename TEXT,
+  imported_by VARCHAR(255) NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE payments (
+  id UUID PRIMARY KEY,
+  import_id UUID NOT NULL REFERENCES payment_imports(id),
+  -- position in the imported report, starting at 1
+  row INT NOT NULL,
+  paid_at TIMESTAMPTZ NOT NULL,
+  amount NUMERIC(12, 2) NOT NULL,
+  message TEXT NOT NULL,
+  payor_phone VARCHAR(255) NOT NULL,
+  payor_name VARCHAR(255) NOT NULL,
+  payee_phone VARCHAR(255) NOT NULL,
+  payee_name VARCHAR(255) NOT NULL,
+  -- tells identical transactions in the same report apart
+  occurrence INT NOT NULL,
+  order_id TEXT REFERENCES orders(id),
+  status payment_status NOT NULL DEFAULT 'unknown_order',
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  UNIQUE (paid_at, amount, message, payor_phone, payee_phone, occurrence)
+);
+
+CREATE INDEX payments_order_id_idx ON payments (order_id);
@@ -0,0 +1,10 @@
+DROP TABLE payment_allocations;
+DROP TYPE payment_method;
+
+UPDATE payments SET status = 'unknown_order' WHERE status = 'manual';
+ALTER TYPE payment_status RENAME TO payment_status_old;
+CREATE TYPE payment_status AS ENUM ('matched', 'amount_mismatch', 'unknown_order', 'duplicate', 'already_paid');
+ALTER TABLE payments ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE payments ALTER COLUMN status TYPE payment_status USING status::text::payment_status;
+ALTER TABLE payments ALTER COLUMN status SET DEFAULT 'unknown_order';
+DROP TYPE payment_status_old;
@@ -0,0 +1,35 @@
+ALTER TYPE payment_status ADD VALUE 'manual';
+
+CREATE TYPE payment_method AS ENUM ('swish', 'cash', 'bank_transfer', 'other');
+
+-- Which orders a payment pays for. Rows are never deleted; undoing a match
+-- sets removed_at instead.
+CREATE TABLE payment_allocations (
+  id UUID PRIMARY KEY,
+  -- NULL for payments made outside of Swish, e.g. in cash
+  payment_id UUID REFERENCES payments(id),
+  order_id TEXT NOT NULL REFERENCES orders(id),
+  amount NUMERIC(12, 2) NOT NULL CHECK (amount > 0),
+  method payment_method NOT NULL,
+  note TEXT,
+  -- NULL when matched automatically
+  created_by VARCHAR(255),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  removed_by VARCHAR(255),
+  removed_at TIMESTAMPTZ
+);
+
+CREATE INDEX payment_allocations_payment_id_idx ON payment_allocations (payment_id);
+CREATE INDEX payment_allocations_order_id_idx ON payment_allocations (order_id);
+
+INSERT INTO payment_allocations (id, payment_id, order_id, amount, method, created_at)
+SELECT gen_random_uuid(), id, order_id, amount, 'swish', paid_at
+FROM payments
+WHERE status = 'matched';
+
+-- orders paid before the payments ledger existed
+INSERT INTO payment_allocations (id, order_id, amount, method, note, created_at)
+SELECT gen_random_uuid(), id, amount, 'swish', 'paid before payments were recorded', paid_at
+FROM orders o
+WHERE paid_at IS NOT NULL AND amount > 0
+  AND NOT EXISTS (SELECT 1 FROM payment_allocations a WHERE a.order_id = o.id);
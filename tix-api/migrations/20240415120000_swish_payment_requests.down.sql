@@ -0,0 +1,2 @@
+DROP TABLE swish_payment_requests;
+DROP TYPE swish_request_status;
@@ -0,0 +1,22 @@
+CREATE TYPE swish_request_status AS ENUM ('created', 'paid', 'declined', 'error', 'cancelled');
+
+-- Payment requests created through the Swish Commerce API
+CREATE TABLE swish_payment_requests (
+  -- instruction UUID chosen by us, which Swish uses as the id
+  id TEXT PRIMARY KEY,
+  order_id TEXT NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+  amount NUMERIC(12, 2) NOT NULL,
+  -- for opening the Swish app when the payer's number is unknown
+  token TEXT,
+  status swish_request_status NOT NULL DEFAULT 'created',
+  payment_reference TEXT,
+  payer_alias TEXT,
+  error_code TEXT,
+  error_message TEXT,
+  paid_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX ON swish_payment_requests (order_id);
+CREATE INDEX ON swish_payment_requests (created_at) WHERE status = 'created';
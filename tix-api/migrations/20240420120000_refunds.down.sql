@@ -0,0 +1,5 @@
+DROP TABLE refunds;
+
+ALTER TABLE tickets DROP COLUMN canceled_by;
+ALTER TABLE tickets DROP COLUMN canceled_at;
+ALTER TABLE tickets DROP COLUMN price;
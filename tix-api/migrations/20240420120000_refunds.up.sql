@@ -0,0 +1,23 @@
+-- what each ticket cost, so that canceling one takes the right amount off
+-- its order even if the ticket type's price has changed since
+ALTER TABLE tickets ADD COLUMN price INT;
+UPDATE tickets t SET price = tt.price FROM ticket_types tt WHERE tt.id = t.ticket_type_id;
+ALTER TABLE tickets ALTER COLUMN price SET NOT NULL;
+
+ALTER TABLE tickets ADD COLUMN canceled_at TIMESTAMPTZ;
+-- unset when canceled by the customer
+ALTER TABLE tickets ADD COLUMN canceled_by TEXT;
+
+CREATE TABLE refunds (
+  id UUID PRIMARY KEY,
+  order_id TEXT NOT NULL REFERENCES orders(id),
+  amount NUMERIC(12, 2) NOT NULL CHECK (amount > 0),
+  method payment_method NOT NULL,
+  -- e.g. the Swish payment reference of the refund
+  reference TEXT,
+  note TEXT,
+  approved_by TEXT NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX ON refunds (order_id);
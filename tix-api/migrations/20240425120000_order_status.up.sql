@@ -0,0 +1,31 @@
+-- the state of an order, kept in step with its timestamps, which record when
+-- it got there. Transitions are checked by tix_api::order::OrderStatus.
+ALTER TABLE orders ADD COLUMN status TEXT;
+
+UPDATE orders o SET status = CASE
+    WHEN canceled_at IS NOT NULL AND paid_at IS NOT NULL THEN 'refunded'
+    WHEN canceled_at IS NOT NULL THEN 'canceled'
+    WHEN paid_at IS NULL THEN 'reserved'
+    WHEN NOT EXISTS (
+      SELECT 1 FROM tickets t
+      WHERE t.order_id = o.id AND t.canceled_at IS NULL AND t.scanned_at IS NULL
+    ) THEN 'completed'
+    WHEN emailed_at IS NOT NULL THEN 'ticketed'
+    ELSE 'paid'
+  END;
+
+ALTER TABLE orders ALTER COLUMN status SET NOT NULL;
+ALTER TABLE orders ALTER COLUMN status SET DEFAULT 'reserved';
+
+ALTER TABLE orders ADD CONSTRAINT orders_status_check CHECK (
+  status IN ('reserved', 'paid', 'ticketed', 'completed', 'expired', 'canceled', 'refunded')
+);
+ALTER TABLE orders ADD CONSTRAINT orders_status_paid_check CHECK (
+  (status IN ('paid', 'ticketed', 'completed', 'refunded')) = (paid_at IS NOT NULL)
+);
+ALTER TABLE orders ADD CONSTRAINT orders_status_emailed_check CHECK (
+  status <> 'ticketed' OR emailed_at IS NOT NULL
+);
+ALTER TABLE orders ADD CONSTRAINT orders_status_canceled_check CHECK (
+  (status IN ('expired', 'canceled', 'refunded')) = (canceled_at IS NOT NULL)
+);
@@ -0,0 +1,3 @@
+DROP TABLE order_events;
+DROP FUNCTION order_events_append_only;
+DROP TYPE order_event_kind;
@@ -0,0 +1,35 @@
+CREATE TYPE order_event_kind AS ENUM (
+  'created',
+  'completed',
+  'canceled',
+  'ticket_canceled',
+  'paid',
+  'emailed',
+  'ticket_scanned'
+);
+
+CREATE TABLE order_events (
+  id UUID PRIMARY KEY,
+  order_id TEXT NOT NULL REFERENCES orders(id),
+  kind order_event_kind NOT NULL,
+  -- the ticket that was canceled or scanned
+  ticket_id UUID REFERENCES tickets(id),
+  -- what the order's status was after the change
+  status TEXT NOT NULL,
+  -- unset for customers
+  actor TEXT,
+  actor_role staff_role,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp()
+);
+
+CREATE INDEX ON order_events (order_id, created_at);
+
+CREATE FUNCTION order_events_append_only() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'order_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER order_events_append_only
+  BEFORE UPDATE OR DELETE ON order_events
+  FOR EACH ROW EXECUTE FUNCTION order_events_append_only();
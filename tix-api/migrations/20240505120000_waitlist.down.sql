@@ -0,0 +1,8 @@
+DROP TABLE waitlist;
+DROP TYPE waitlist_status;
+
+DELETE FROM email_templates WHERE kind = 'waitlist_offer';
+ALTER TYPE email_kind RENAME TO email_kind_old;
+CREATE TYPE email_kind AS ENUM ('order_confirmation', 'payment_reminder', 'tickets');
+ALTER TABLE email_templates ALTER COLUMN kind TYPE email_kind USING kind::text::email_kind;
+DROP TYPE email_kind_old;
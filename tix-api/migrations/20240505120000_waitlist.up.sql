@@ -0,0 +1,26 @@
+ALTER TYPE email_kind ADD VALUE 'waitlist_offer';
+
+CREATE TYPE waitlist_status AS ENUM ('waiting', 'offered', 'claimed', 'lapsed');
+
+CREATE TABLE waitlist (
+  id UUID PRIMARY KEY,
+  event_id UUID NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+  ticket_type_id UUID NOT NULL REFERENCES ticket_types(id) ON DELETE CASCADE,
+  count INT NOT NULL CHECK (count > 0),
+  email TEXT NOT NULL,
+  name TEXT NOT NULL,
+  phone TEXT NOT NULL,
+  locale TEXT NOT NULL DEFAULT 'sv' CHECK (locale IN ('sv', 'en')),
+  status waitlist_status NOT NULL DEFAULT 'waiting',
+  -- secret part of the claim link, set when the tickets are offered
+  token TEXT,
+  offered_at TIMESTAMPTZ,
+  offer_expires_at TIMESTAMPTZ,
+  -- the order the offer was claimed with
+  order_id TEXT REFERENCES orders(id),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX ON waitlist (event_id, created_at) WHERE status = 'waiting';
+-- offered tickets are held, and counted whenever what is remaining is
+CREATE INDEX ON waitlist (ticket_type_id) WHERE status = 'offered';
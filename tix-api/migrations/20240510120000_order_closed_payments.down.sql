@@ -0,0 +1,7 @@
+UPDATE payments SET status = 'matched' WHERE status = 'order_closed';
+ALTER TYPE payment_status RENAME TO payment_status_old;
+CREATE TYPE payment_status AS ENUM ('matched', 'amount_mismatch', 'unknown_order', 'duplicate', 'already_paid', 'manual');
+ALTER TABLE payments ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE payments ALTER COLUMN status TYPE payment_status USING status::text::payment_status;
+ALTER TABLE payments ALTER COLUMN status SET DEFAULT 'unknown_order';
+DROP TYPE payment_status_old;
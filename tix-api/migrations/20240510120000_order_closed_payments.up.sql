@@ -0,0 +1 @@
+ALTER TYPE payment_status ADD VALUE 'order_closed';
@@ -0,0 +1,2 @@
+ALTER TYPE order_event_kind RENAME VALUE 'checked_out' TO 'completed';
+ALTER TABLE orders RENAME COLUMN checked_out_at TO completed_at;
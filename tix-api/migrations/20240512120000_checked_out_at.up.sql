@@ -0,0 +1,4 @@
+-- completed_at recorded when the customer finished checking out, which is
+-- not the same as an order being completed by having every ticket scanned
+ALTER TABLE orders RENAME COLUMN completed_at TO checked_out_at;
+ALTER TYPE order_event_kind RENAME VALUE 'completed' TO 'checked_out';
@@ -0,0 +1,23 @@
+-- events that cannot be told apart from others in the old kinds are dropped
+ALTER TABLE order_events DISABLE TRIGGER order_events_append_only;
+DELETE FROM order_events WHERE kind IN ('payment_removed', 'refunded', 'completed');
+ALTER TABLE order_events ENABLE TRIGGER order_events_append_only;
+
+ALTER TYPE order_event_kind RENAME TO order_event_kind_old;
+CREATE TYPE order_event_kind AS ENUM (
+  'created',
+  'checked_out',
+  'canceled',
+  'ticket_canceled',
+  'paid',
+  'emailed',
+  'ticket_scanned'
+);
+ALTER TABLE order_events ALTER COLUMN kind TYPE order_event_kind USING (
+  CASE kind::text
+    WHEN 'payment_added' THEN 'paid'
+    WHEN 'expired' THEN 'canceled'
+    ELSE kind::text
+  END
+)::order_event_kind;
+DROP TYPE order_event_kind_old;
@@ -0,0 +1,8 @@
+-- every change to the status or payments of an order is recorded, not only
+-- those made through the order routes. 'paid' was recorded when an imported
+-- payment was allocated to the order
+ALTER TYPE order_event_kind RENAME VALUE 'paid' TO 'payment_added';
+ALTER TYPE order_event_kind ADD VALUE 'payment_removed';
+ALTER TYPE order_event_kind ADD VALUE 'refunded';
+ALTER TYPE order_event_kind ADD VALUE 'completed';
+ALTER TYPE order_event_kind ADD VALUE 'expired';
@@ -0,0 +1,7 @@
+UPDATE payments SET status = 'unknown_order' WHERE status = 'outgoing';
+ALTER TYPE payment_status RENAME TO payment_status_old;
+CREATE TYPE payment_status AS ENUM ('matched', 'amount_mismatch', 'unknown_order', 'duplicate', 'already_paid', 'manual', 'order_closed');
+ALTER TABLE payments ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE payments ALTER COLUMN status TYPE payment_status USING status::text::payment_status;
+ALTER TABLE payments ALTER COLUMN status SET DEFAULT 'unknown_order';
+DROP TYPE payment_status_old;
@@ -1,90 +1,425 @@
+//! Customer emails. These only build messages; they are delivered through
+//! the [outbox](crate::outbox).
+//!
+//! Every email is rendered from a [MiniJinja](minijinja) template with a
+//! plain-text body and an optional HTML alternative. The defaults live in
+//! `templates/email/<locale>` and can be overridden per event.
+
 use lettre::{
-    message::{Mailbox, SinglePart},
-    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart},
+    Message,
 };
+use minijinja::Environment;
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, Duration, OffsetDateTime};
+use time_tz::OffsetDateTimeExt;
+use uuid::Uuid;
 
 use crate::{
+    error::{Code, ResponseError},
+    event::Event,
+    locale::Locale,
     order::{Order, OrderId},
-    swish::ELEVKAREN_NR,
+    qr,
+    swish::request::PaymentRequest,
+    waitlist::WaitlistEntry,
 };
 
-pub async fn send_order_confirmation(
-    mailer: &AsyncSmtpTransport<Tokio1Executor>,
-    to: Mailbox,
-    order: &Order,
-) -> Result<(), lettre::transport::smtp::Error> {
-    let from: Mailbox = "STHLM VISION <sthlmvision@sodralat.in>".parse().unwrap();
-    let message = Message::builder()
-        .from(from.clone())
-        .to(to)
-        .bcc(from)
-        .subject(format!("Order {}", order.id))
-        .singlepart(SinglePart::plain(indoc::formatdoc!(
-            "
-                Hej {name}!
+/// Content id of the Swish QR code, which HTML templates can show with
+/// `<img src="cid:swish-qr">`.
+const SWISH_QR_CID: &str = "swish-qr";
+
+/// Sender details and links shared by all emails.
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    pub from: Mailbox,
+    pub reply_to: Option<Mailbox>,
+    /// Swish number customers pay to.
+    pub payee: String,
+    /// Base URL of the frontend, without a trailing slash.
+    pub frontend_url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "email_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum EmailKind {
+    OrderConfirmation,
+    PaymentReminder,
+    /// Sent with the printable tickets attached.
+    Tickets,
+    /// Offers freed tickets to someone on the [waitlist](crate::waitlist).
+    /// Not about an order, so its templates get a [`WaitlistContext`].
+    WaitlistOffer,
+}
+
+impl EmailKind {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::OrderConfirmation => "order_confirmation",
+            Self::PaymentReminder => "payment_reminder",
+            Self::Tickets => "tickets",
+            Self::WaitlistOffer => "waitlist_offer",
+        }
+    }
+
+    /// Whether the sender gets a copy, to keep track of what has been sent.
+    const fn bcc_sender(self) -> bool {
+        matches!(self, Self::OrderConfirmation | Self::Tickets)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmailError {
+    #[error("invalid template: {0:#}")]
+    Template(#[from] minijinja::Error),
+    #[error("invalid email address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Qr(#[from] qrcode::types::QrError),
+}
+
+impl From<EmailError> for ResponseError {
+    fn from(e: EmailError) -> Self {
+        match e {
+            EmailError::Template(_) => Self::new(Code::InvalidTemplate, e.to_string()),
+            EmailError::Address(_) => Self::new(Code::InvalidEmail, e.to_string()),
+            EmailError::Database(e) => e.into(),
+            EmailError::Qr(e) => e.into(),
+        }
+    }
+}
+
+/// Template sources for one kind of email.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmailTemplate {
+    pub subject: String,
+    pub text: String,
+    pub html: Option<String>,
+}
+
+/// A rendered email, before it is turned into a [`Message`].
+#[derive(Debug, Serialize)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub text: String,
+    pub html: Option<String>,
+}
+
+impl EmailTemplate {
+    /// The built-in template for `kind` in `locale`.
+    pub fn default_for(kind: EmailKind, locale: Locale) -> Self {
+        macro_rules! template {
+            ($locale:literal, $kind:literal) => {
+                (
+                    include_str!(concat!(
+                        "../templates/email/",
+                        $locale,
+                        "/",
+                        $kind,
+                        ".subject.txt"
+                    )),
+                    include_str!(concat!("../templates/email/", $locale, "/", $kind, ".txt")),
+                    include_str!(concat!("../templates/email/", $locale, "/", $kind, ".html")),
+                )
+            };
+        }
+
+        let (subject, text, html) = match (locale, kind) {
+            (Locale::Sv, EmailKind::OrderConfirmation) => template!("sv", "order_confirmation"),
+            (Locale::Sv, EmailKind::PaymentReminder) => template!("sv", "payment_reminder"),
+            (Locale::Sv, EmailKind::Tickets) => template!("sv", "tickets"),
+            (Locale::Sv, EmailKind::WaitlistOffer) => template!("sv", "waitlist_offer"),
+            (Locale::En, EmailKind::OrderConfirmation) => template!("en", "order_confirmation"),
+            (Locale::En, EmailKind::PaymentReminder) => template!("en", "payment_reminder"),
+            (Locale::En, EmailKind::Tickets) => template!("en", "tickets"),
+            (Locale::En, EmailKind::WaitlistOffer) => template!("en", "waitlist_offer"),
+        };
+
+        Self {
+            subject: subject.to_owned(),
+            text: text.to_owned(),
+            html: Some(html.to_owned()),
+        }
+    }
+
+    /// Check that every part compiles, without rendering anything.
+    pub fn validate(&self) -> Result<(), minijinja::Error> {
+        let mut env = Environment::new();
+        env.add_template("subject.txt", &self.subject)?;
+        env.add_template("body.txt", &self.text)?;
+        if let Some(html) = &self.html {
+            env.add_template("body.html", html)?;
+        }
+
+        Ok(())
+    }
+
+    /// Render with a [`Context`], or a [`WaitlistContext`] for waitlist
+    /// offers.
+    pub fn render(&self, context: &impl Serialize) -> Result<RenderedEmail, minijinja::Error> {
+        // the .html name turns on autoescaping
+        let env = Environment::new();
+        let subject = env.render_named_str("subject.txt", &self.subject, context)?;
+        let text = env.render_named_str("body.txt", &self.text, context)?;
+        let html = self
+            .html
+            .as_deref()
+            .map(|html| env.render_named_str("body.html", html, context))
+            .transpose()?;
 
-                Dina biljetter har reserverats och skickas när evenemanget börjar närmar sig.
+        Ok(RenderedEmail {
+            // headers cannot span several lines
+            subject: subject.split_whitespace().collect::<Vec<_>>().join(" "),
+            text,
+            html,
+        })
+    }
+}
+
+/// The template used for `kind` emails in `locale` about `event_id`, which is
+/// either an override stored for the event or the default.
+pub async fn template(
+    executor: impl sqlx::PgExecutor<'_>,
+    event_id: Uuid,
+    kind: EmailKind,
+    locale: Locale,
+) -> sqlx::Result<EmailTemplate> {
+    let template = sqlx::query_as!(
+        EmailTemplate,
+        "SELECT subject, text, html FROM email_templates WHERE event_id = $1 AND kind = $2 AND locale = $3",
+        event_id,
+        kind as EmailKind,
+        locale.as_str(),
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(template.unwrap_or_else(|| EmailTemplate::default_for(kind, locale)))
+}
 
-                Om du inte redan gjort det: swisha {amount} kr till {payee} och skriv ordernumret ({id}) i meddelandefältet. Ordern kan komma att avbrytas om betalning inte sker inom 24 timmar.
+#[derive(Debug, Serialize)]
+struct EventContext<'a> {
+    id: Uuid,
+    name: &'a str,
+    venue: &'a str,
+    starts_at: String,
+}
 
-                Vid eventuella frågor är du välkommen att svara på detta mejl eller skicka ett meddelande till @elevkaren på Instagram.
+/// Variables available to templates.
+#[derive(Debug, Serialize)]
+pub struct Context<'a> {
+    order: &'a Order,
+    event: EventContext<'a>,
+    /// Swish number to pay to.
+    payee: &'a str,
+    payment_deadline_hours: i64,
+    /// When the order is canceled unless it has been paid.
+    payment_deadline: String,
+    tickets_url: String,
+    /// Prefilled payment to the [payee](Self::payee).
+    swish: PaymentRequest,
+}
 
-                Vi ses!
+fn format_local(time: OffsetDateTime) -> String {
+    time.to_timezone(time_tz::timezones::db::europe::STOCKHOLM)
+        .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+        .unwrap()
+}
 
-                🤸
-            ",
-            name = order.name,
-            id = order.id,
-            amount = order.amount,
-            payee = ELEVKAREN_NR,
-        )))
+impl<'a> Context<'a> {
+    pub fn new(
+        config: &'a EmailConfig,
+        event: &'a Event,
+        order: &'a Order,
+        payment_deadline: Duration,
+    ) -> Self {
+        #[derive(Serialize)]
+        struct Query<'a> {
+            id: &'a OrderId,
+            email: &'a str,
+        }
+
+        let query = serde_urlencoded::to_string(Query {
+            id: &order.id,
+            email: &order.email,
+        })
         .unwrap();
 
-    mailer.send(message).await?;
+        Self {
+            order,
+            event: EventContext {
+                id: event.id,
+                name: &event.name,
+                venue: &event.venue,
+                starts_at: format_local(event.starts_at),
+            },
+            payee: &config.payee,
+            payment_deadline_hours: payment_deadline.whole_hours(),
+            payment_deadline: format_local(order.created_at + payment_deadline),
+            tickets_url: format!("{}/tickets?{query}", config.frontend_url),
+            swish: PaymentRequest::for_order(&config.payee, order),
+        }
+    }
+}
+
+/// Variables available to [`EmailKind::WaitlistOffer`] templates.
+#[derive(Debug, Serialize)]
+pub struct WaitlistContext<'a> {
+    entry: &'a WaitlistEntry,
+    event: EventContext<'a>,
+    /// Name of the ticket type offered.
+    ticket_type: &'a str,
+    /// Where the offered tickets are ordered.
+    claim_url: String,
+    /// When the offer lapses.
+    expires_at: Option<String>,
+}
+
+impl<'a> WaitlistContext<'a> {
+    pub fn new(
+        config: &'a EmailConfig,
+        event: &'a Event,
+        ticket_type: &'a str,
+        entry: &'a WaitlistEntry,
+    ) -> Self {
+        #[derive(Serialize)]
+        struct Query<'a> {
+            id: Uuid,
+            token: &'a str,
+        }
+
+        let query = serde_urlencoded::to_string(Query {
+            id: entry.id,
+            token: entry.token.as_deref().unwrap_or_default(),
+        })
+        .unwrap();
 
-    Ok(())
+        Self {
+            entry,
+            event: EventContext {
+                id: event.id,
+                name: &event.name,
+                venue: &event.venue,
+                starts_at: format_local(event.starts_at),
+            },
+            ticket_type,
+            claim_url: format!("{}/waitlist?{query}", config.frontend_url),
+            expires_at: entry.offer_expires_at.map(format_local),
+        }
+    }
 }
 
-pub async fn send_tickets(
-    mailer: &AsyncSmtpTransport<Tokio1Executor>,
+/// Build the message for an already rendered email, with `attachment` added
+/// if there is one. `inline` are images referenced from the HTML part.
+pub fn message(
+    config: &EmailConfig,
+    kind: EmailKind,
     to: Mailbox,
-    order: &Order,
-) -> Result<(), lettre::transport::smtp::Error> {
-    #[derive(serde::Serialize)]
-    struct Query<'a> {
-        id: &'a OrderId,
-        email: &'a str,
+    email: RenderedEmail,
+    inline: Vec<SinglePart>,
+    attachment: Option<SinglePart>,
+) -> Message {
+    let mut builder = Message::builder()
+        .from(config.from.clone())
+        .to(to)
+        .subject(email.subject);
+
+    if let Some(reply_to) = &config.reply_to {
+        builder = builder.reply_to(reply_to.clone());
+    }
+    if kind.bcc_sender() {
+        builder = builder.bcc(config.from.clone());
     }
 
-    let query = serde_urlencoded::to_string(Query {
-        id: &order.id,
-        email: &order.email,
-    })
-    .unwrap();
+    let text = SinglePart::plain(email.text);
+    let alternative = |html| {
+        let html = SinglePart::html(html);
+        let alternative = MultiPart::alternative().singlepart(text.clone());
 
-    let from: Mailbox = "STHLM VISION <sthlmvision@sodralat.in>".parse().unwrap();
-    let message = Message::builder()
-        .from(from.clone())
-        .to(to)
-        .bcc(from)
-        .subject(format!("Biljetter till order {}", order.id))
-        .singlepart(SinglePart::plain(indoc::formatdoc!(
-            "
-                Hej igen {name}!
+        if inline.is_empty() {
+            alternative.singlepart(html)
+        } else {
+            let related = inline
+                .iter()
+                .cloned()
+                .fold(MultiPart::related().singlepart(html), |related, part| {
+                    related.singlepart(part)
+                });
+            alternative.multipart(related)
+        }
+    };
 
-                Klicka på länken för att visa dina biljetter: https://sthlmvision.sodralat.in/tickets?{query}
+    match (email.html, attachment) {
+        (None, None) => builder.singlepart(text),
+        (Some(html), None) => builder.multipart(alternative(html)),
+        (None, Some(attachment)) => {
+            builder.multipart(MultiPart::mixed().singlepart(text).singlepart(attachment))
+        }
+        (Some(html), Some(attachment)) => builder.multipart(
+            MultiPart::mixed()
+                .multipart(alternative(html))
+                .singlepart(attachment),
+        ),
+    }
+    .unwrap()
+}
 
-                Vid eventuella frågor är du välkommen att svara på detta mejl eller skicka ett meddelande till @elevkaren på Instagram.
+/// Render and build a `kind` email about `order`, using the event's template
+/// in the order's locale.
+pub async fn compose(
+    executor: impl sqlx::PgExecutor<'_>,
+    config: &EmailConfig,
+    kind: EmailKind,
+    event: &Event,
+    order: &Order,
+    payment_deadline: Duration,
+    attachment: Option<SinglePart>,
+) -> Result<Message, EmailError> {
+    let email = template(executor, event.id, kind, order.locale)
+        .await?
+        .render(&Context::new(config, event, order, payment_deadline))?;
+    let to = Mailbox::new(Some(order.name.trim().to_owned()), order.email.parse()?);
 
-                Allt gott!
+    let mut inline = Vec::new();
+    if email
+        .html
+        .as_deref()
+        .is_some_and(|html| html.contains(&format!("cid:{SWISH_QR_CID}")))
+    {
+        let png = qr::png(&PaymentRequest::for_order(&config.payee, order).qr_data)?;
+        inline.push(
+            Attachment::new_inline(SWISH_QR_CID.to_owned())
+                .body(png, ContentType::parse("image/png").unwrap()),
+        );
+    }
 
-                🤸
-            ",
-            name = order.name.trim(),
-        )))
-        .unwrap();
+    Ok(message(config, kind, to, email, inline, attachment))
+}
+
+/// Render and build the email offering `entry` its tickets, using the
+/// event's template in the entry's locale.
+pub async fn compose_waitlist_offer(
+    executor: impl sqlx::PgExecutor<'_>,
+    config: &EmailConfig,
+    event: &Event,
+    ticket_type: &str,
+    entry: &WaitlistEntry,
+) -> Result<Message, EmailError> {
+    let kind = EmailKind::WaitlistOffer;
+    let email = template(executor, event.id, kind, entry.locale)
+        .await?
+        .render(&WaitlistContext::new(config, event, ticket_type, entry))?;
+    let to = Mailbox::new(Some(entry.name.trim().to_owned()), entry.email.parse()?);
 
-    mailer.send(message).await?;
+    Ok(message(config, kind, to, email, Vec::new(), None))
+}
 
-    Ok(())
+/// The printable tickets of `order`, for attaching to a [`EmailKind::Tickets`]
+/// email.
+pub fn tickets_attachment(order: &Order, pdf: Vec<u8>) -> SinglePart {
+    Attachment::new(format!("biljetter-{}.pdf", order.id))
+        .body(pdf, ContentType::parse("application/pdf").unwrap())
 }
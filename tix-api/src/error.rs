@@ -8,21 +8,74 @@ pub enum Code {
     InternalError,
     OrderNotFound,
     TooManyTickets,
-    OrderCompleted,
+    OrderNotCancelable,
     InvalidIdToken,
     MissingCookie,
     TicketNotFound,
+    EventNotFound,
+    SalesClosed,
+    EventHasOrders,
+    Forbidden,
+    StaffNotFound,
+    TicketTypeNotFound,
+    TicketTypeHasTickets,
+    NoTicketsRequested,
+    InvalidTicketCode,
+    OutboxEntryNotFound,
+    InvalidTemplate,
+    InvalidEmail,
+    PaymentNotFound,
+    AllocationNotFound,
+    InvalidAllocation,
+    InvalidUpload,
+    InvalidSwishReport,
+    InvalidRefund,
+    InvalidOrderTransition,
+    WaitlistEntryNotFound,
+    OfferUnavailable,
+    OfferClaimed,
+    OrderNotPayable,
+    TicketScanned,
+    TicketsAvailable,
+    AlreadyOffered,
 }
 
 impl Code {
     const fn status(&self) -> StatusCode {
         match self {
             Self::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
-            Self::OrderNotFound | Self::TicketNotFound => StatusCode::NOT_FOUND,
-            Self::TooManyTickets | Self::OrderCompleted | Self::InvalidIdToken => {
-                StatusCode::BAD_REQUEST
-            }
+            Self::OrderNotFound
+            | Self::TicketNotFound
+            | Self::EventNotFound
+            | Self::StaffNotFound
+            | Self::TicketTypeNotFound
+            | Self::OutboxEntryNotFound
+            | Self::PaymentNotFound
+            | Self::AllocationNotFound
+            | Self::WaitlistEntryNotFound => StatusCode::NOT_FOUND,
+            Self::TooManyTickets
+            | Self::OrderNotCancelable
+            | Self::InvalidIdToken
+            | Self::SalesClosed
+            | Self::NoTicketsRequested
+            | Self::InvalidTicketCode
+            | Self::InvalidTemplate
+            | Self::InvalidEmail
+            | Self::InvalidAllocation
+            | Self::InvalidUpload
+            | Self::InvalidSwishReport
+            | Self::InvalidRefund
+            | Self::OfferUnavailable => StatusCode::BAD_REQUEST,
             Self::MissingCookie => StatusCode::UNAUTHORIZED,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::EventHasOrders
+            | Self::TicketTypeHasTickets
+            | Self::InvalidOrderTransition
+            | Self::OfferClaimed
+            | Self::OrderNotPayable
+            | Self::TicketScanned
+            | Self::TicketsAvailable
+            | Self::AlreadyOffered => StatusCode::CONFLICT,
         }
     }
 }
@@ -30,6 +83,9 @@ impl Code {
 pub struct ResponseError {
     pub code: Code,
     pub message: String,
+    /// Extra information for the client. Errors with details are returned as
+    /// JSON instead of plain text.
+    pub details: Option<serde_json::Value>,
 }
 
 impl ResponseError {
@@ -37,13 +93,39 @@ impl ResponseError {
         Self {
             code,
             message: message.into(),
+            details: None,
         }
     }
+
+    /// Attach `details`, which must serialize to a JSON object.
+    pub fn with_details(mut self, details: impl Serialize) -> Self {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'a Code,
+    message: &'a str,
+    #[serde(flatten)]
+    details: &'a serde_json::Value,
 }
 
 impl IntoResponse for ResponseError {
     fn into_response(self) -> axum::response::Response {
-        (self.code.status(), self.message).into_response()
+        match &self.details {
+            Some(details) => (
+                self.code.status(),
+                axum::Json(ErrorBody {
+                    code: &self.code,
+                    message: &self.message,
+                    details,
+                }),
+            )
+                .into_response(),
+            None => (self.code.status(), self.message).into_response(),
+        }
     }
 }
 
@@ -54,6 +136,13 @@ impl From<lettre::transport::smtp::Error> for ResponseError {
     }
 }
 
+impl From<qrcode::types::QrError> for ResponseError {
+    fn from(e: qrcode::types::QrError) -> Self {
+        error!(?e);
+        Self::new(Code::InternalError, "qr code error")
+    }
+}
+
 impl From<sqlx::Error> for ResponseError {
     fn from(e: sqlx::Error) -> Self {
         error!(?e);
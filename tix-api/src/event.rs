@@ -0,0 +1,131 @@
+use axum::{
+    extract::{FromRef, Path},
+    http::request::Parts,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    error::{Code, ResponseError},
+    routes::AppState,
+};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Event {
+    pub id: Uuid,
+    pub name: String,
+    pub venue: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub starts_at: OffsetDateTime,
+    /// Total number of tickets for sale, across all ticket types.
+    pub capacity: i32,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub sales_start: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub sales_end: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+impl Event {
+    /// Whether tickets can be bought at `now`.
+    pub fn is_on_sale(&self, now: OffsetDateTime) -> bool {
+        self.sales_start.is_none_or(|start| start <= now)
+            && self.sales_end.is_none_or(|end| now < end)
+    }
+}
+
+/// A category of tickets for an event, e.g. standard, student or VIP.
+#[derive(Debug, Serialize, Clone)]
+pub struct TicketType {
+    pub id: Uuid,
+    pub event_id: Uuid,
+    pub name: String,
+    /// Price per ticket in SEK.
+    pub price: i32,
+    /// Number of tickets of this type for sale. The event capacity still
+    /// applies on top of this.
+    pub quota: i32,
+    /// Non-public types (e.g. staff comps) can only be ordered by admins.
+    pub public: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EventError {
+    #[error("event not found")]
+    EventNotFound,
+    #[error("ticket sales are closed")]
+    SalesClosed,
+    #[error("ticket type not found")]
+    TicketTypeNotFound,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl From<EventError> for ResponseError {
+    fn from(value: EventError) -> Self {
+        let code = match value {
+            EventError::EventNotFound => Code::EventNotFound,
+            EventError::SalesClosed => Code::SalesClosed,
+            EventError::TicketTypeNotFound => Code::TicketTypeNotFound,
+            EventError::Database(e) => return e.into(),
+        };
+
+        Self::new(code, value.to_string())
+    }
+}
+
+pub async fn get(executor: impl sqlx::PgExecutor<'_>, id: Uuid) -> Result<Event, EventError> {
+    sqlx::query_as!(Event, "SELECT * FROM events WHERE id = $1", id)
+        .fetch_optional(executor)
+        .await?
+        .ok_or(EventError::EventNotFound)
+}
+
+/// Like [`get`], but also locks the event until the end of the transaction.
+/// Orders for the event are created while holding the lock, so that two
+/// customers cannot both be sold the last tickets.
+pub async fn lock(executor: impl sqlx::PgExecutor<'_>, id: Uuid) -> Result<Event, EventError> {
+    sqlx::query_as!(Event, "SELECT * FROM events WHERE id = $1 FOR UPDATE", id)
+        .fetch_optional(executor)
+        .await?
+        .ok_or(EventError::EventNotFound)
+}
+
+pub async fn ticket_types(
+    executor: impl sqlx::PgExecutor<'_>,
+    event_id: Uuid,
+) -> sqlx::Result<Vec<TicketType>> {
+    sqlx::query_as!(
+        TicketType,
+        "SELECT * FROM ticket_types WHERE event_id = $1 ORDER BY price DESC, name ASC",
+        event_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[axum::async_trait]
+impl<S> axum::extract::FromRequestParts<S> for Event
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ResponseError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        #[derive(Debug, Deserialize)]
+        struct PathParams {
+            event_id: Uuid,
+        }
+
+        let Path(PathParams { event_id }) = Path::from_request_parts(parts, &state)
+            .await
+            .map_err(|_| EventError::EventNotFound)?;
+
+        Ok(get(&AppState::from_ref(state).pool, event_id).await?)
+    }
+}
@@ -0,0 +1,143 @@
+//! Background task that cancels orders that have not been paid in time.
+
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+use tracing::{error, info};
+
+use crate::{
+    email::{self, EmailConfig, EmailError, EmailKind},
+    event::{self, EventError},
+    order::{self, Order, OrderStatus},
+    outbox,
+};
+
+/// How often the task looks for orders to remind or expire.
+const INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExpiryConfig {
+    /// How long an order may stay unpaid before it is canceled.
+    pub payment_deadline: Duration,
+    /// How long after ordering a reminder is sent, if at all.
+    pub reminder_after: Option<Duration>,
+}
+
+/// Queue a payment reminder for every unpaid order created before `cutoff`
+/// that has not already been reminded. Returns the number of reminders queued.
+pub async fn remind_unpaid_orders(
+    pool: &PgPool,
+    email: &EmailConfig,
+    cutoff: OffsetDateTime,
+    payment_deadline: Duration,
+) -> sqlx::Result<usize> {
+    let mut tx = pool.begin().await?;
+    let orders = sqlx::query_as!(
+        Order,
+        r#"SELECT
+      id,
+      email,
+      name,
+      phone,
+      amount,
+      created_at,
+      checked_out_at,
+      paid_at,
+      canceled_at,
+      emailed_at,
+      event_id,
+      reminded_at,
+      status AS "status: OrderStatus",
+      locale
+    FROM orders
+    WHERE status = $2 AND checked_out_at IS NULL AND reminded_at IS NULL AND created_at < $1"#,
+        cutoff,
+        OrderStatus::Reserved.as_str(),
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut count = 0;
+
+    for order in orders {
+        let event = match event::get(&mut *tx, order.event_id).await {
+            Ok(event) => event,
+            Err(EventError::Database(err)) => return Err(err),
+            Err(err) => {
+                error!(order_id = %order.id, "cannot remind: {err}");
+                continue;
+            }
+        };
+
+        let message = match email::compose(
+            &mut *tx,
+            email,
+            EmailKind::PaymentReminder,
+            &event,
+            &order,
+            payment_deadline,
+            None,
+        )
+        .await
+        {
+            Ok(message) => message,
+            Err(EmailError::Database(err)) => return Err(err),
+            Err(err) => {
+                error!(order_id = %order.id, "cannot remind: {err}");
+                continue;
+            }
+        };
+        outbox::enqueue(
+            &mut *tx,
+            EmailKind::PaymentReminder.as_str(),
+            Some(&order.id),
+            &message,
+        )
+        .await?;
+
+        sqlx::query!(
+            "UPDATE orders SET reminded_at = NOW() WHERE id = $1",
+            order.id.as_ref()
+        )
+        .execute(&mut *tx)
+        .await?;
+        count += 1;
+    }
+
+    tx.commit().await?;
+
+    Ok(count)
+}
+
+async fn tick(pool: &PgPool, config: ExpiryConfig, email: &EmailConfig) -> sqlx::Result<()> {
+    let now = OffsetDateTime::now_utc();
+
+    if let Some(reminder_after) = config.reminder_after {
+        let count =
+            remind_unpaid_orders(pool, email, now - reminder_after, config.payment_deadline)
+                .await?;
+        if count > 0 {
+            info!("queued {count} payment reminders");
+        }
+    }
+
+    let mut tx = pool.begin().await?;
+    for order in order::expire_unpaid(&mut tx, now - config.payment_deadline).await? {
+        info!(order_id = %order.id, email = order.email, "canceled unpaid order");
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Periodically remind and expire unpaid orders. Never returns.
+pub async fn run(pool: PgPool, config: ExpiryConfig, email: EmailConfig) {
+    let mut interval = tokio::time::interval(INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = tick(&pool, config, &email).await {
+            error!("order expiry failed: {err}");
+        }
+    }
+}
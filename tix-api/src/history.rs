@@ -0,0 +1,111 @@
+//! Append-only record of who changed an order, and how.
+//!
+//! Every change to an order is written here in the same transaction as the
+//! change itself, by the functions in [`order`](crate::order) that make it.
+//! Rows are never updated or deleted, which a trigger on the table enforces.
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    order::{OrderId, OrderStatus},
+    routes::auth::Identity,
+    staff::Role,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "order_event_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum OrderEventKind {
+    Created,
+    /// The customer finished checking out, see
+    /// [`Order::checked_out_at`](crate::order::Order::checked_out_at).
+    CheckedOut,
+    Canceled,
+    TicketCanceled,
+    /// A payment was [allocated](crate::payment::Allocation) to the order.
+    /// Whether that paid for it shows in the status.
+    PaymentAdded,
+    /// An allocation was undone.
+    PaymentRemoved,
+    /// Money was paid back to the customer, see [`refund`](crate::refund).
+    Refunded,
+    /// The tickets were emailed.
+    Emailed,
+    TicketScanned,
+    /// Every ticket left was scanned when the others were canceled.
+    Completed,
+    /// Not paid in time.
+    Expired,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderEvent {
+    pub id: Uuid,
+    pub order_id: OrderId,
+    pub kind: OrderEventKind,
+    /// The ticket that was canceled or scanned.
+    pub ticket_id: Option<Uuid>,
+    /// The status of the order after the change.
+    pub status: OrderStatus,
+    /// Email of the staff member who made the change, unset for customers.
+    pub actor: Option<String>,
+    pub actor_role: Option<Role>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+pub struct NewOrderEvent<'a> {
+    pub order_id: &'a OrderId,
+    pub kind: OrderEventKind,
+    pub ticket_id: Option<Uuid>,
+    pub status: OrderStatus,
+    pub actor: Option<&'a Identity>,
+}
+
+pub async fn record(
+    executor: impl sqlx::PgExecutor<'_>,
+    event: NewOrderEvent<'_>,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        "INSERT INTO order_events (id, order_id, kind, ticket_id, status, actor, actor_role)
+    VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        Uuid::new_v4(),
+        event.order_id.as_ref(),
+        event.kind as OrderEventKind,
+        event.ticket_id,
+        event.status.as_str(),
+        event.actor.map(|i| i.email.as_str()),
+        event.actor.map(|i| i.role) as Option<Role>,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// The history of an order, oldest first.
+pub async fn for_order(
+    executor: impl sqlx::PgExecutor<'_>,
+    order_id: &OrderId,
+) -> sqlx::Result<Vec<OrderEvent>> {
+    sqlx::query_as!(
+        OrderEvent,
+        r#"SELECT
+      id,
+      order_id,
+      kind AS "kind: OrderEventKind",
+      ticket_id,
+      status AS "status: OrderStatus",
+      actor,
+      actor_role AS "actor_role: Role",
+      created_at
+    FROM order_events
+    WHERE order_id = $1
+    ORDER BY created_at, id"#,
+        order_id.as_ref(),
+    )
+    .fetch_all(executor)
+    .await
+}
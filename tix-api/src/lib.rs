@@ -1,6 +1,20 @@
 pub mod email;
 pub mod error;
+pub mod event;
+pub mod expiry;
+pub mod history;
+pub mod locale;
+pub mod mailer;
 pub mod oidc;
 pub mod order;
+pub mod outbox;
+pub mod payment;
+pub mod pdf;
+pub mod qr;
+pub mod reconcile;
+pub mod refund;
 pub mod routes;
+pub mod staff;
 pub mod swish;
+pub mod ticket_code;
+pub mod waitlist;
@@ -0,0 +1,30 @@
+//! Languages that customer emails are available in.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    Sv,
+    En,
+}
+
+impl Locale {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Sv => "sv",
+            Self::En => "en",
+        }
+    }
+}
+
+/// Locales are stored as text, with a check constraint keeping them valid.
+impl From<String> for Locale {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "en" => Self::En,
+            _ => Self::Sv,
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Mail transports. Production uses SMTP, while local development can spool
+//! messages to `.eml` files or keep them in memory.
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use lettre::{address::Envelope, AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MailError {
+    #[error(transparent)]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl MailError {
+    /// Whether retrying is pointless.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            Self::Smtp(e) => e.is_permanent(),
+            Self::Io(_) => false,
+        }
+    }
+}
+
+#[axum::async_trait]
+pub trait Mailer: Send + Sync {
+    /// Deliver an already formatted message.
+    async fn send_raw(&self, envelope: &Envelope, message: &[u8]) -> Result<(), MailError>;
+}
+
+pub struct SmtpMailer(pub AsyncSmtpTransport<Tokio1Executor>);
+
+#[axum::async_trait]
+impl Mailer for SmtpMailer {
+    async fn send_raw(&self, envelope: &Envelope, message: &[u8]) -> Result<(), MailError> {
+        self.0.send_raw(envelope, message).await?;
+        Ok(())
+    }
+}
+
+/// Writes every message to `<dir>/<random uuid>.eml`.
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[axum::async_trait]
+impl Mailer for FileMailer {
+    async fn send_raw(&self, envelope: &Envelope, message: &[u8]) -> Result<(), MailError> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.dir.join(format!("{}.eml", Uuid::new_v4()));
+        tokio::fs::write(&path, message).await?;
+        info!(to = ?envelope.to(), "wrote email to {}", path.display());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SentMail {
+    pub envelope: Envelope,
+    pub message: Vec<u8>,
+}
+
+/// Keeps every message in memory, mostly useful in tests.
+#[derive(Default, Clone)]
+pub struct MemoryMailer {
+    sent: Arc<Mutex<Vec<SentMail>>>,
+}
+
+impl MemoryMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything sent so far, oldest first.
+    pub fn sent(&self) -> Vec<SentMail> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[axum::async_trait]
+impl Mailer for MemoryMailer {
+    async fn send_raw(&self, envelope: &Envelope, message: &[u8]) -> Result<(), MailError> {
+        info!(to = ?envelope.to(), "kept email in memory");
+        self.sent.lock().unwrap().push(SentMail {
+            envelope: envelope.clone(),
+            message: message.to_vec(),
+        });
+        Ok(())
+    }
+}
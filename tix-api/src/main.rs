@@ -1,30 +1,113 @@
-use std::sync::Arc;
+use std::{num::NonZeroUsize, path::PathBuf, sync::Arc};
 
+use anyhow::Context;
 use axum_extra::extract::cookie::Key;
 use base64::{engine::general_purpose::STANDARD, Engine};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use lettre::{
+    message::Mailbox,
     transport::smtp::{authentication::Credentials, extension::ClientId},
     AsyncSmtpTransport, Tokio1Executor,
 };
 
 use sqlx::PgPool;
-use tix_api::{oidc::Oidc, routes::AppState};
+use time::Duration;
+use tix_api::{
+    email::EmailConfig,
+    expiry::ExpiryConfig,
+    mailer::{FileMailer, Mailer, MemoryMailer, SmtpMailer},
+    oidc::Oidc,
+    routes::AppState,
+    swish::{
+        self,
+        commerce::{self, CommerceConfig},
+    },
+    ticket_code::TicketSigner,
+};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum MailTransport {
+    /// Send through an SMTP relay.
+    Smtp,
+    /// Write `.eml` files to `--mail-spool-dir`.
+    File,
+    /// Keep emails in memory and only log them.
+    Memory,
+}
 
 #[derive(Debug, Parser)]
 struct Options {
     #[clap(long, env, hide_env_values = true)]
     database_url: String,
+    #[clap(long, env, value_enum, default_value_t = MailTransport::Smtp)]
+    mail_transport: MailTransport,
+    #[clap(long, env, default_value = "mail")]
+    mail_spool_dir: PathBuf,
+    #[clap(long, env, default_value = "smtp-relay.gmail.com")]
+    smtp_relay: String,
+    #[clap(long, env, default_value = "sthlmvision.fly.dev")]
+    smtp_hello_name: String,
+    /// Sender of customer emails.
+    #[clap(long, env, default_value = "STHLM VISION <sthlmvision@sodralat.in>")]
+    mail_from: Mailbox,
+    /// Where replies to customer emails go, if not to the sender.
+    #[clap(long, env)]
+    mail_reply_to: Option<Mailbox>,
+    /// Swish number customers pay to.
+    #[clap(long, env, default_value = "123-345 69 51")]
+    swish_payee: String,
+    /// Most tickets that can be bought in one order.
+    #[clap(long, env, default_value = "10")]
+    max_tickets_per_order: NonZeroUsize,
+    /// Base URL of the frontend, used for links in emails.
+    #[clap(long, env, default_value = "https://sthlmvision.sodralat.in")]
+    frontend_url: String,
+    /// Required when sending through SMTP.
     #[clap(long, env)]
-    gmail_username: String,
+    gmail_username: Option<String>,
+    /// Required when sending through SMTP.
     #[clap(long, env, hide_env_values = true)]
-    gmail_password: String,
+    gmail_password: Option<String>,
     #[clap(long, env)]
     next_public_google_client_id: String,
     #[clap(long, env, hide_env_values = true)]
     google_client_secret: String,
     #[clap(long, env, hide_env_values = true)]
     cookie_key: String,
+    /// Base64-encoded 32 byte Ed25519 secret key used to sign ticket codes.
+    #[clap(long, env, hide_env_values = true)]
+    ticket_signing_key: String,
+    /// Emails that are always granted the admin role.
+    #[clap(long, env, value_delimiter = ',')]
+    admin_emails: Vec<String>,
+    /// Hours an order may stay unpaid before it is canceled.
+    #[clap(long, env, default_value_t = 24)]
+    payment_deadline_hours: u32,
+    /// Hours after ordering at which unpaid orders get a payment reminder.
+    /// No reminders are sent if unset.
+    #[clap(long, env)]
+    payment_reminder_hours: Option<u32>,
+    /// Hours that tickets offered to someone on the waitlist are held for
+    /// them.
+    #[clap(long, env, default_value_t = 24)]
+    waitlist_claim_hours: u32,
+    /// Base URL of the Swish Commerce API, e.g.
+    /// `https://cpc.getswish.net/swish-cpcapi`. Payment requests are only
+    /// made through the API if set.
+    #[clap(long, env)]
+    swish_api_url: Option<String>,
+    /// Public URL of `/payments/swish/callback`. Required with the API.
+    #[clap(long, env)]
+    swish_callback_url: Option<String>,
+    /// Merchant certificate for the API, as PKCS #12. Only a local mock of
+    /// the API accepts requests without one.
+    #[clap(long, env)]
+    swish_certificate: Option<PathBuf>,
+    #[clap(long, env, hide_env_values = true, default_value = "")]
+    swish_certificate_password: String,
+    /// PEM root certificate to trust for the API, for its test environment.
+    #[clap(long, env)]
+    swish_root_certificate: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -32,27 +115,100 @@ async fn main() -> anyhow::Result<()> {
     _ = dotenvy::dotenv();
     tracing_subscriber::fmt::init();
     let options = Options::parse();
-    let smtp = AsyncSmtpTransport::<Tokio1Executor>::relay("smtp-relay.gmail.com")?
-        .credentials(Credentials::new(
-            options.gmail_username,
-            options.gmail_password,
-        ))
-        .hello_name(ClientId::Domain("sthlmvision.fly.dev".into()))
-        .build();
+    let mailer: Arc<dyn Mailer> = match options.mail_transport {
+        MailTransport::Smtp => {
+            let smtp = AsyncSmtpTransport::<Tokio1Executor>::relay(&options.smtp_relay)?
+                .credentials(Credentials::new(
+                    options
+                        .gmail_username
+                        .context("gmail username is required for smtp")?,
+                    options
+                        .gmail_password
+                        .context("gmail password is required for smtp")?,
+                ))
+                .hello_name(ClientId::Domain(options.smtp_hello_name))
+                .build();
+            Arc::new(SmtpMailer(smtp))
+        }
+        MailTransport::File => Arc::new(FileMailer::new(options.mail_spool_dir)),
+        MailTransport::Memory => Arc::new(MemoryMailer::new()),
+    };
     let oidc = Arc::new(Oidc {
         client_id: openidconnect::ClientId::new(options.next_public_google_client_id),
         client_secret: openidconnect::ClientSecret::new(options.google_client_secret),
     });
     let cookie_key = Key::try_from(&STANDARD.decode(options.cookie_key)?[..])?;
+    let ticket_signing_key: [u8; 32] = STANDARD
+        .decode(options.ticket_signing_key)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("ticket signing key must be 32 bytes"))?;
+    let ticket_signer = Arc::new(TicketSigner::new(&ticket_signing_key));
 
     let pool = PgPool::connect(&options.database_url).await?;
     sqlx::migrate!().run(&pool).await?;
+    tix_api::staff::ensure_admins(&pool, &options.admin_emails).await?;
+
+    let email = EmailConfig {
+        from: options.mail_from,
+        reply_to: options.mail_reply_to,
+        payee: options.swish_payee.clone(),
+        frontend_url: options.frontend_url.trim_end_matches('/').to_owned(),
+    };
+
+    let expiry = ExpiryConfig {
+        payment_deadline: Duration::hours(options.payment_deadline_hours.into()),
+        reminder_after: options
+            .payment_reminder_hours
+            .map(|hours| Duration::hours(hours.into())),
+    };
+    tokio::spawn(tix_api::expiry::run(pool.clone(), expiry, email.clone()));
+    tokio::spawn(tix_api::outbox::run(pool.clone(), mailer));
+    tokio::spawn(tix_api::waitlist::run(
+        pool.clone(),
+        email.clone(),
+        Duration::hours(options.waitlist_claim_hours.into()),
+    ));
+
+    let swish = match options.swish_api_url {
+        Some(api_url) => {
+            let config = CommerceConfig {
+                api_url: api_url.trim_end_matches('/').to_owned(),
+                payee_alias: swish::alias(&options.swish_payee),
+                callback_url: options
+                    .swish_callback_url
+                    .context("swish callback url is required with the swish api")?,
+            };
+            let client = match options.swish_certificate {
+                Some(path) => {
+                    let root = options
+                        .swish_root_certificate
+                        .map(std::fs::read)
+                        .transpose()?;
+                    commerce::Client::with_certificate(
+                        config,
+                        &std::fs::read(path)?,
+                        &options.swish_certificate_password,
+                        root.as_deref(),
+                    )?
+                }
+                None => commerce::Client::new(reqwest::Client::new(), config),
+            };
+            let client = Arc::new(client);
+            tokio::spawn(commerce::run(pool.clone(), client.clone()));
+            Some(client)
+        }
+        None => None,
+    };
 
     let app = tix_api::routes::routes().with_state(AppState {
         pool,
-        smtp,
         oidc,
         cookie_key,
+        payment_deadline: expiry.payment_deadline,
+        ticket_signer,
+        email: Arc::new(email),
+        max_tickets_per_order: options.max_tickets_per_order,
+        swish,
     });
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await?;
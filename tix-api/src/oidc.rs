@@ -242,7 +242,7 @@ impl Oidc {
 
 //     Ok(user)
 // }
-trait LocalizedClaimExt {
+pub trait LocalizedClaimExt {
     fn default_locale(&self) -> Option<String>;
 }
 
@@ -1,20 +1,34 @@
-use std::{fmt, iter};
+use std::{fmt, iter, str::FromStr};
 
 use axum::{
     extract::{FromRef, Path, Query},
     http::request::Parts,
 };
 use rand::Rng;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use sqlx::{
+    error::BoxDynError,
+    postgres::{PgTypeInfo, PgValueRef},
+    PgConnection, Postgres,
+};
 use time::OffsetDateTime;
+use uuid::Uuid;
 
 use crate::{
     error::{Code, ResponseError},
+    history::{self, NewOrderEvent, OrderEventKind},
+    locale::Locale,
+    payment,
     routes::{auth::Identity, AppState},
+    staff::Role,
 };
 
+/// Characters that are hard to mistake for one another, used for order ids.
+const CHARSET: &[u8] = b"CDEFHJKMNPRTVWXY2345689";
+pub(crate) const ORDER_ID_LEN: usize = 8;
+
 fn unambiguous_str(len: usize) -> String {
-    const CHARSET: &[u8] = b"CDEFHJKMNPRTVWXY2345689";
     let mut rng = rand::thread_rng();
     iter::repeat_with(|| CHARSET[rng.gen_range(0..CHARSET.len())] as char)
         .take(len)
@@ -40,7 +54,30 @@ impl AsRef<str> for OrderId {
 #[allow(clippy::new_without_default)]
 impl OrderId {
     pub fn new() -> Self {
-        Self(unambiguous_str(8))
+        Self(unambiguous_str(ORDER_ID_LEN))
+    }
+
+    /// Interpret an order id typed by a person, e.g. in a Swish message.
+    /// Case and whitespace are ignored and characters that are left out of
+    /// order ids are read as the one they are most likely mistaken for.
+    pub fn normalize(s: &str) -> Option<Self> {
+        let id = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .map(|c| match c.to_ascii_uppercase() {
+                'O' | 'Q' | '0' => 'D',
+                'A' => '4',
+                'B' => '8',
+                'G' => '6',
+                'S' => '5',
+                'Z' => '2',
+                'U' => 'V',
+                '7' => 'T',
+                c => c,
+            })
+            .collect::<String>();
+
+        (id.len() == ORDER_ID_LEN && id.bytes().all(|b| CHARSET.contains(&b))).then_some(Self(id))
     }
 }
 
@@ -50,7 +87,134 @@ impl From<String> for OrderId {
     }
 }
 
-#[derive(Debug, Serialize)]
+/// Where an order is in its life. Orders start out reserved and only move
+/// along the transitions in [`OrderStatus::can_become`], which are also what
+/// the bulk updates in SQL are limited to.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    /// Tickets are held for the customer until the order is paid or expires.
+    Reserved,
+    Paid,
+    /// The tickets have been emailed.
+    Ticketed,
+    /// Every ticket has been scanned.
+    Completed,
+    /// Not paid in time.
+    Expired,
+    /// Canceled before being paid.
+    Canceled,
+    /// Canceled after being paid. The payment is returned through
+    /// [refunds](crate::refund), which can still be due.
+    Refunded,
+}
+
+impl OrderStatus {
+    pub const ALL: [Self; 7] = [
+        Self::Reserved,
+        Self::Paid,
+        Self::Ticketed,
+        Self::Completed,
+        Self::Expired,
+        Self::Canceled,
+        Self::Refunded,
+    ];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Reserved => "reserved",
+            Self::Paid => "paid",
+            Self::Ticketed => "ticketed",
+            Self::Completed => "completed",
+            Self::Expired => "expired",
+            Self::Canceled => "canceled",
+            Self::Refunded => "refunded",
+        }
+    }
+
+    /// Whether an order can go from this status to `next`. Paid orders go
+    /// back to reserved if their payment is unallocated.
+    pub const fn can_become(self, next: Self) -> bool {
+        use OrderStatus::*;
+
+        matches!(
+            (self, next),
+            (Reserved, Paid | Expired | Canceled)
+                | (Paid, Reserved | Ticketed | Completed | Refunded)
+                | (Ticketed, Reserved | Completed | Refunded)
+        )
+    }
+
+    /// The statuses that can become `next`, for limiting updates in SQL.
+    pub fn sources(next: Self) -> Vec<String> {
+        Self::ALL
+            .into_iter()
+            .filter(|s| s.can_become(next))
+            .map(|s| s.as_str().to_owned())
+            .collect()
+    }
+
+    /// Whether the tickets of the order are valid.
+    pub const fn is_paid(self) -> bool {
+        matches!(self, Self::Paid | Self::Ticketed | Self::Completed)
+    }
+
+    /// What an order in this status becomes when it is canceled: refunded if
+    /// it has been paid.
+    pub const fn on_cancel(self) -> Self {
+        if self.is_paid() {
+            Self::Refunded
+        } else {
+            Self::Canceled
+        }
+    }
+
+    /// Whether the order has been called off, and its tickets released.
+    pub const fn is_canceled(self) -> bool {
+        matches!(self, Self::Expired | Self::Canceled | Self::Refunded)
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid order status {0:?}")]
+pub struct InvalidOrderStatus(String);
+
+impl FromStr for OrderStatus {
+    type Err = InvalidOrderStatus;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| InvalidOrderStatus(s.to_owned()))
+    }
+}
+
+/// Statuses are stored as text, with a check constraint keeping them valid.
+/// Columns are read with `status AS "status: OrderStatus"`.
+impl sqlx::Type<Postgres> for OrderStatus {
+    fn type_info() -> PgTypeInfo {
+        <str as sqlx::Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <str as sqlx::Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl sqlx::Decode<'_, Postgres> for OrderStatus {
+    fn decode(value: PgValueRef<'_>) -> Result<Self, BoxDynError> {
+        Ok(<&str as sqlx::Decode<Postgres>>::decode(value)?.parse()?)
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
 pub struct Order {
     pub id: OrderId,
     pub email: String,
@@ -59,14 +223,353 @@ pub struct Order {
     pub amount: i32,
     #[serde(with = "time::serde::rfc3339")]
     pub created_at: OffsetDateTime,
+    /// When the customer finished checking out, saying that they have paid.
+    /// Reserved orders are no longer expired after this, see
+    /// [`expire_unpaid`].
     #[serde(with = "time::serde::rfc3339::option")]
-    pub completed_at: Option<OffsetDateTime>,
+    pub checked_out_at: Option<OffsetDateTime>,
     #[serde(with = "time::serde::rfc3339::option")]
     pub paid_at: Option<OffsetDateTime>,
     #[serde(with = "time::serde::rfc3339::option")]
     pub canceled_at: Option<OffsetDateTime>,
     #[serde(with = "time::serde::rfc3339::option")]
     pub emailed_at: Option<OffsetDateTime>,
+    pub event_id: Uuid,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub reminded_at: Option<OffsetDateTime>,
+    /// Language of the emails sent about the order.
+    pub locale: Locale,
+    pub status: OrderStatus,
+}
+
+impl Order {
+    /// Whether the customer can still cancel the order, or tickets of it,
+    /// themselves. Once they have paid or said that they have, only admins
+    /// can, since the money has to be refunded.
+    pub fn customer_can_cancel(&self) -> bool {
+        !self.status.is_paid() && self.checked_out_at.is_none()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum OrderError {
+    #[error("order cannot go from {from} to {to}")]
+    InvalidTransition { from: OrderStatus, to: OrderStatus },
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl From<OrderError> for ResponseError {
+    fn from(e: OrderError) -> Self {
+        match e {
+            OrderError::InvalidTransition { .. } => {
+                Self::new(Code::InvalidOrderTransition, e.to_string())
+            }
+            OrderError::Database(e) => e.into(),
+        }
+    }
+}
+
+/// What changed an order and who did it, recorded in its
+/// [history](crate::history) along with the status the order ended up in.
+#[derive(Debug, Clone, Copy)]
+pub struct Change<'a> {
+    pub kind: OrderEventKind,
+    /// The ticket that was canceled or scanned.
+    pub ticket_id: Option<Uuid>,
+    /// Unset for customers and background tasks.
+    pub actor: Option<&'a Identity>,
+}
+
+impl<'a> Change<'a> {
+    pub fn new(kind: OrderEventKind, actor: Option<&'a Identity>) -> Self {
+        Self {
+            kind,
+            ticket_id: None,
+            actor,
+        }
+    }
+}
+
+/// Record a change to `order` that has already been made.
+pub async fn record(
+    executor: impl sqlx::PgExecutor<'_>,
+    order: &Order,
+    change: Change<'_>,
+) -> sqlx::Result<()> {
+    history::record(
+        executor,
+        NewOrderEvent {
+            order_id: &order.id,
+            kind: change.kind,
+            ticket_id: change.ticket_id,
+            status: order.status,
+            actor: change.actor,
+        },
+    )
+    .await
+}
+
+pub async fn get(executor: impl sqlx::PgExecutor<'_>, id: &OrderId) -> sqlx::Result<Option<Order>> {
+    sqlx::query_as!(
+        Order,
+        r#"SELECT
+      id,
+      email,
+      name,
+      phone,
+      amount,
+      created_at,
+      checked_out_at,
+      paid_at,
+      canceled_at,
+      emailed_at,
+      event_id,
+      reminded_at,
+      status AS "status: OrderStatus",
+      locale
+    FROM orders
+    WHERE id = $1"#,
+        id.as_ref(),
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// The order `id`, locked until the end of the transaction.
+pub async fn lock(
+    executor: impl sqlx::PgExecutor<'_>,
+    id: &OrderId,
+) -> sqlx::Result<Option<Order>> {
+    sqlx::query_as!(
+        Order,
+        r#"SELECT
+      id,
+      email,
+      name,
+      phone,
+      amount,
+      created_at,
+      checked_out_at,
+      paid_at,
+      canceled_at,
+      emailed_at,
+      event_id,
+      reminded_at,
+      status AS "status: OrderStatus",
+      locale
+    FROM orders
+    WHERE id = $1
+    FOR UPDATE"#,
+        id.as_ref(),
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Move `order` to the `next` status, stamping the time it got there, and
+/// record `change`. Fails if the transition is not allowed, or if the order
+/// has changed status since it was read.
+pub async fn transition(
+    conn: &mut PgConnection,
+    order: &Order,
+    next: OrderStatus,
+    change: Change<'_>,
+) -> Result<Order, OrderError> {
+    transition_at(conn, order, next, OffsetDateTime::now_utc(), change).await
+}
+
+/// Like [`transition`], stamping the time as `at`, e.g. when a payment was
+/// made rather than when it was imported.
+async fn transition_at(
+    conn: &mut PgConnection,
+    order: &Order,
+    next: OrderStatus,
+    at: OffsetDateTime,
+    change: Change<'_>,
+) -> Result<Order, OrderError> {
+    let invalid = OrderError::InvalidTransition {
+        from: order.status,
+        to: next,
+    };
+    if !order.status.can_become(next) {
+        return Err(invalid);
+    }
+
+    let order = sqlx::query_as!(
+        Order,
+        r#"UPDATE orders SET
+      status = $3,
+      paid_at = CASE
+        WHEN $3 = 'reserved' THEN NULL
+        WHEN $3 = 'paid' THEN COALESCE(paid_at, $4)
+        ELSE paid_at
+      END,
+      emailed_at = CASE WHEN $3 = 'ticketed' THEN COALESCE(emailed_at, $4) ELSE emailed_at END,
+      canceled_at = CASE
+        WHEN $3 IN ('expired', 'canceled', 'refunded') THEN COALESCE(canceled_at, $4)
+        ELSE canceled_at
+      END
+    WHERE id = $1 AND status = $2
+    RETURNING
+      id,
+      email,
+      name,
+      phone,
+      amount,
+      created_at,
+      checked_out_at,
+      paid_at,
+      canceled_at,
+      emailed_at,
+      event_id,
+      reminded_at,
+      status AS "status: OrderStatus",
+      locale"#,
+        order.id.as_ref(),
+        order.status.as_str(),
+        next.as_str(),
+        at,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(invalid)?;
+    record(&mut *conn, &order, change).await?;
+
+    Ok(order)
+}
+
+/// Mark `order` as paid at `paid_at` if its allocations cover the amount, or
+/// as reserved again if they no longer do. An order that is already paid
+/// keeps its original time and status. Orders that have been canceled or
+/// completed are left alone. `order` must be locked. `change` is recorded
+/// whether the status changed or not.
+pub async fn refresh_paid(
+    conn: &mut PgConnection,
+    order: &Order,
+    paid_at: OffsetDateTime,
+    change: Change<'_>,
+) -> Result<Order, OrderError> {
+    let covered =
+        payment::allocated_to_order(&mut *conn, &order.id).await? >= Decimal::from(order.amount);
+
+    let next = match order.status {
+        OrderStatus::Reserved if covered => OrderStatus::Paid,
+        OrderStatus::Paid | OrderStatus::Ticketed if !covered => OrderStatus::Reserved,
+        _ => {
+            record(&mut *conn, order, change).await?;
+            return Ok(order.clone());
+        }
+    };
+
+    transition_at(conn, order, next, paid_at, change).await
+}
+
+/// Take `amount` off what `order` costs, e.g. for a canceled ticket. See
+/// [`refresh_paid`] for whether that pays for it.
+pub async fn reduce_amount(
+    executor: impl sqlx::PgExecutor<'_>,
+    order: &Order,
+    amount: i32,
+) -> sqlx::Result<Order> {
+    sqlx::query_as!(
+        Order,
+        r#"UPDATE orders SET amount = amount - $2 WHERE id = $1
+    RETURNING
+      id,
+      email,
+      name,
+      phone,
+      amount,
+      created_at,
+      checked_out_at,
+      paid_at,
+      canceled_at,
+      emailed_at,
+      event_id,
+      reminded_at,
+      status AS "status: OrderStatus",
+      locale"#,
+        order.id.as_ref(),
+        amount,
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Record that the customer has checked out `order`. Returns `None` if they
+/// already had, leaving the order as it was.
+pub async fn check_out(
+    executor: impl sqlx::PgExecutor<'_>,
+    order: &Order,
+) -> sqlx::Result<Option<Order>> {
+    sqlx::query_as!(
+        Order,
+        r#"UPDATE orders SET checked_out_at = NOW() WHERE id = $1 AND checked_out_at IS NULL
+    RETURNING
+      id,
+      email,
+      name,
+      phone,
+      amount,
+      created_at,
+      checked_out_at,
+      paid_at,
+      canceled_at,
+      emailed_at,
+      event_id,
+      reminded_at,
+      status AS "status: OrderStatus",
+      locale"#,
+        order.id.as_ref(),
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Expire the orders created before `cutoff` that are still reserved, freeing
+/// their tickets. Orders the customer has checked out are left alone, since
+/// their payment may be on its way.
+pub async fn expire_unpaid(
+    conn: &mut PgConnection,
+    cutoff: OffsetDateTime,
+) -> sqlx::Result<Vec<Order>> {
+    let orders = sqlx::query_as!(
+        Order,
+        r#"UPDATE orders SET status = $2, canceled_at = NOW()
+    WHERE status = ANY($3) AND checked_out_at IS NULL AND created_at < $1
+    RETURNING
+      id,
+      email,
+      name,
+      phone,
+      amount,
+      created_at,
+      checked_out_at,
+      paid_at,
+      canceled_at,
+      emailed_at,
+      event_id,
+      reminded_at,
+      status AS "status: OrderStatus",
+      locale"#,
+        cutoff,
+        OrderStatus::Expired.as_str(),
+        &OrderStatus::sources(OrderStatus::Expired),
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for order in &orders {
+        record(
+            &mut *conn,
+            order,
+            Change::new(OrderEventKind::Expired, None),
+        )
+        .await?;
+    }
+
+    Ok(orders)
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -104,25 +607,24 @@ where
 
         let Path(PathParams { order_id }) = Path::from_request_parts(parts, &state).await.unwrap();
 
-        let provided_email = if Identity::from_request_parts(parts, state).await.is_err() {
+        let is_staff = Identity::from_request_parts(parts, state)
+            .await
+            .is_ok_and(|identity| identity.require(&[Role::Admin, Role::Viewer]).is_ok());
+
+        let provided_email = if !is_staff {
             // unauthenticated
             let Query(QueryParams { email }) =
                 Query::from_request_parts(parts, &state).await.unwrap();
             Some(email)
         } else {
-            // admin authenticated, no need to check query params
+            // staff authenticated, no need to check query params
             None
         };
 
         let pool = AppState::from_ref(state).pool;
-        let order = sqlx::query_as!(
-            Order,
-            "SELECT * FROM orders WHERE id = $1",
-            order_id.as_ref(),
-        )
-        .fetch_optional(&pool)
-        .await?
-        .ok_or(ExtractOrderError::OrderNotFound)?;
+        let order = get(&pool, &order_id)
+            .await?
+            .ok_or(ExtractOrderError::OrderNotFound)?;
 
         match provided_email {
             Some(provided_email) if provided_email == order.email => Ok(order),
@@ -0,0 +1,224 @@
+//! Transactional outbox for emails.
+//!
+//! Emails are written to the `outbox` table in the same transaction as the
+//! change that caused them, and delivered afterwards by [`run`]. A failed
+//! delivery is retried with exponential backoff until [`MAX_ATTEMPTS`] is
+//! reached, after which the message is marked as dead and left for an admin
+//! to look at.
+//!
+//! Messages are claimed before they are sent by pushing their next attempt
+//! [`LEASE`] into the future, so that no lock is held while talking to the
+//! mail server. A worker that dies mid-batch leaves its messages to be picked
+//! up again once the lease runs out.
+
+use std::sync::Arc;
+
+use lettre::{address::Envelope, Message};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    mailer::{MailError, Mailer},
+    order::OrderId,
+};
+
+/// How often the worker looks for messages to deliver.
+const INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+/// Maximum number of messages delivered per batch.
+const BATCH_SIZE: i64 = 20;
+/// How long a claimed message is left alone before it is tried again, in
+/// case the worker that claimed it never records the outcome.
+const LEASE: Duration = Duration::minutes(5);
+/// Deliveries attempted before a message is considered dead.
+pub const MAX_ATTEMPTS: i32 = 8;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "outbox_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxStatus {
+    Pending,
+    Sent,
+    /// Delivery failed permanently or too many times.
+    Dead,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutboxEntry {
+    pub id: Uuid,
+    pub kind: String,
+    pub order_id: Option<OrderId>,
+    pub envelope_to: Vec<String>,
+    pub status: OutboxStatus,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub next_attempt_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub sent_at: Option<OffsetDateTime>,
+}
+
+/// Queue `message` for delivery. `kind` describes what sort of email it is,
+/// e.g. `order_confirmation`.
+pub async fn enqueue(
+    executor: impl sqlx::PgExecutor<'_>,
+    kind: &str,
+    order_id: Option<&OrderId>,
+    message: &Message,
+) -> sqlx::Result<()> {
+    let envelope = message.envelope();
+    let to = envelope
+        .to()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+    sqlx::query!(
+        "INSERT INTO outbox (id, kind, order_id, envelope_from, envelope_to, message)
+    VALUES ($1, $2, $3, $4, $5, $6)",
+        Uuid::new_v4(),
+        kind,
+        order_id.map(AsRef::as_ref),
+        envelope.from().map(ToString::to_string),
+        &to,
+        message.formatted(),
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+enum DeliveryError {
+    #[error("invalid envelope: {0}")]
+    Envelope(String),
+    #[error(transparent)]
+    Mail(#[from] MailError),
+}
+
+impl DeliveryError {
+    /// Whether retrying is pointless.
+    fn is_permanent(&self) -> bool {
+        match self {
+            Self::Envelope(_) => true,
+            Self::Mail(e) => e.is_permanent(),
+        }
+    }
+}
+
+async fn send(
+    mailer: &dyn Mailer,
+    from: Option<&str>,
+    to: &[String],
+    message: &[u8],
+) -> Result<(), DeliveryError> {
+    let from = from
+        .map(str::parse)
+        .transpose()
+        .map_err(|e| DeliveryError::Envelope(format!("{e}")))?;
+    let to = to
+        .iter()
+        .map(|a| a.parse())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| DeliveryError::Envelope(format!("{e}")))?;
+    let envelope = Envelope::new(from, to).map_err(|e| DeliveryError::Envelope(e.to_string()))?;
+
+    mailer.send_raw(&envelope, message).await?;
+
+    Ok(())
+}
+
+/// Delay before attempt number `attempts + 1`.
+fn backoff(attempts: i32) -> Duration {
+    Duration::minutes(1 << attempts.clamp(0, 6))
+}
+
+/// Deliver a batch of due messages. Returns the number of messages sent.
+pub async fn deliver_pending(pool: &PgPool, mailer: &dyn Mailer) -> sqlx::Result<usize> {
+    // SKIP LOCKED lets several instances run the worker side by side, and the
+    // lease keeps them from taking the same messages once the claim commits
+    let rows = sqlx::query!(
+        "UPDATE outbox SET attempts = attempts + 1, next_attempt_at = $2
+    WHERE id IN (
+      SELECT id
+      FROM outbox
+      WHERE status = 'pending' AND next_attempt_at <= NOW()
+      ORDER BY next_attempt_at
+      LIMIT $1
+      FOR UPDATE SKIP LOCKED
+    )
+    RETURNING id, kind, envelope_from, envelope_to, message, attempts",
+        BATCH_SIZE,
+        OffsetDateTime::now_utc() + LEASE,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut sent = 0;
+
+    for row in rows {
+        let attempts = row.attempts;
+
+        match send(
+            mailer,
+            row.envelope_from.as_deref(),
+            &row.envelope_to,
+            &row.message,
+        )
+        .await
+        {
+            Ok(()) => {
+                sqlx::query!(
+                    "UPDATE outbox SET status = 'sent', sent_at = NOW(), last_error = NULL WHERE id = $1",
+                    row.id,
+                )
+                .execute(pool)
+                .await?;
+                sent += 1;
+            }
+            Err(err) if err.is_permanent() || attempts >= MAX_ATTEMPTS => {
+                error!(id = %row.id, kind = row.kind, "giving up on email: {err}");
+                sqlx::query!(
+                    "UPDATE outbox SET status = 'dead', last_error = $2 WHERE id = $1",
+                    row.id,
+                    err.to_string(),
+                )
+                .execute(pool)
+                .await?;
+            }
+            Err(err) => {
+                warn!(id = %row.id, kind = row.kind, attempts, "failed to send email: {err}");
+                sqlx::query!(
+                    "UPDATE outbox SET last_error = $2, next_attempt_at = $3 WHERE id = $1",
+                    row.id,
+                    err.to_string(),
+                    OffsetDateTime::now_utc() + backoff(attempts),
+                )
+                .execute(pool)
+                .await?;
+            }
+        }
+    }
+
+    Ok(sent)
+}
+
+/// Periodically deliver queued emails. Never returns.
+pub async fn run(pool: PgPool, mailer: Arc<dyn Mailer>) {
+    let mut interval = tokio::time::interval(INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match deliver_pending(&pool, mailer.as_ref()).await {
+            Ok(0) => {}
+            Ok(sent) => info!("sent {sent} emails"),
+            Err(err) => error!("email delivery failed: {err}"),
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Ledger of payments imported from Swish reports.
+//!
+//! Every transaction is stored along with the import it came from, so that
+//! payments can be audited and matched again later. Importing a report that
+//! overlaps an earlier one only adds the transactions that are new.
+//!
+//! What a payment pays for is recorded as [allocations](Allocation), which
+//! also cover payments made outside of Swish. An order is paid once its
+//! allocations add up to the order amount, see
+//! [`refresh_paid`](crate::order::refresh_paid).
+
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    error::{Code, ResponseError},
+    order::OrderId,
+    swish::Transaction,
+};
+
+/// How a payment was matched to an order.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "payment_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    Matched,
+    AmountMismatch,
+    UnknownOrder,
+    Duplicate,
+    AlreadyPaid,
+    /// The order had expired or been canceled. The payment is allocated to
+    /// it, to be refunded or the order reopened.
+    OrderClosed,
+    /// Money paid out of the account, e.g. a refund. Never matched to an
+    /// order.
+    Outgoing,
+    /// Matched or unmatched by an admin, and left alone by automatic matching.
+    Manual,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "payment_method", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentMethod {
+    Swish,
+    Cash,
+    BankTransfer,
+    Other,
+}
+
+/// The part of a payment that pays for an order. A payment may be split
+/// across several orders, and payments made outside of Swish have no
+/// payment at all.
+#[derive(Debug, Serialize)]
+pub struct Allocation {
+    pub id: Uuid,
+    pub payment_id: Option<Uuid>,
+    pub order_id: OrderId,
+    pub amount: Decimal,
+    pub method: PaymentMethod,
+    pub note: Option<String>,
+    /// Unset when matched automatically.
+    pub created_by: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    pub removed_by: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub removed_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PaymentError {
+    #[error("payment not found")]
+    PaymentNotFound,
+    #[error("allocation not found")]
+    AllocationNotFound,
+    #[error("{0}")]
+    InvalidAllocation(&'static str),
+    #[error("{0}")]
+    InvalidRefund(&'static str),
+}
+
+impl From<PaymentError> for ResponseError {
+    fn from(value: PaymentError) -> Self {
+        let code = match value {
+            PaymentError::PaymentNotFound => Code::PaymentNotFound,
+            PaymentError::AllocationNotFound => Code::AllocationNotFound,
+            PaymentError::InvalidAllocation(_) => Code::InvalidAllocation,
+            PaymentError::InvalidRefund(_) => Code::InvalidRefund,
+        };
+
+        Self::new(code, value.to_string())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PaymentImport {
+    pub id: Uuid,
+    pub filename: Option<String>,
+    pub imported_by: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Payment {
+    pub id: Uuid,
+    pub import_id: Uuid,
+    /// Position in the imported report, starting at 1.
+    pub row: i32,
+    #[serde(with = "time::serde::rfc3339")]
+    pub paid_at: OffsetDateTime,
+    pub amount: Decimal,
+    pub message: String,
+    pub payor_phone: String,
+    pub payor_name: String,
+    pub payee_phone: String,
+    pub payee_name: String,
+    pub order_id: Option<OrderId>,
+    pub status: PaymentStatus,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Import {
+    #[serde(flatten)]
+    pub import: PaymentImport,
+    /// Transactions that had already been imported.
+    pub skipped: usize,
+    /// Transactions that were new, not yet matched.
+    #[serde(skip)]
+    pub payments: Vec<Payment>,
+}
+
+/// Store `transactions` as a new import, skipping those that have been
+/// imported before.
+pub async fn import(
+    conn: &mut PgConnection,
+    filename: Option<&str>,
+    imported_by: &str,
+    transactions: Vec<Transaction>,
+) -> sqlx::Result<Import> {
+    let import = sqlx::query_as!(
+        PaymentImport,
+        "INSERT INTO payment_imports (id, filename, imported_by) VALUES ($1, $2, $3) RETURNING *",
+        Uuid::new_v4(),
+        filename,
+        imported_by,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    // identical transactions within a report are told apart by how many
+    // came before them, so that re-importing the report finds the same ones
+    let mut seen = HashMap::<_, i32>::new();
+    let mut payments = Vec::with_capacity(transactions.len());
+    let mut skipped = 0;
+
+    for (i, t) in transactions.into_iter().enumerate() {
+        let occurrence = seen
+            .entry((
+                t.time,
+                t.amount,
+                t.message.clone(),
+                t.payor.phone.clone(),
+                t.payee.phone.clone(),
+            ))
+            .or_default();
+        *occurrence += 1;
+
+        let payment = sqlx::query_as!(
+            Payment,
+            r#"INSERT INTO payments (id, import_id, row, paid_at, amount, message, payor_phone, payor_name, payee_phone, payee_name, occurrence)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+    ON CONFLICT (paid_at, amount, message, payor_phone, payee_phone, occurrence) DO NOTHING
+    RETURNING
+      id,
+      import_id,
+      row,
+      paid_at,
+      amount,
+      message,
+      payor_phone,
+      payor_name,
+      payee_phone,
+      payee_name,
+      order_id AS "order_id: OrderId",
+      status AS "status: PaymentStatus",
+      created_at"#,
+            Uuid::new_v4(),
+            import.id,
+            i as i32 + 1,
+            t.time,
+            t.amount,
+            t.message,
+            t.payor.phone,
+            t.payor.name,
+            t.payee.phone,
+            t.payee.name,
+            *occurrence,
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        match payment {
+            Some(payment) => payments.push(payment),
+            None => skipped += 1,
+        }
+    }
+
+    Ok(Import {
+        import,
+        skipped,
+        payments,
+    })
+}
+
+/// Payments that were not matched to an order with the right amount, oldest
+/// first.
+pub async fn unmatched(executor: impl sqlx::PgExecutor<'_>) -> sqlx::Result<Vec<Payment>> {
+    sqlx::query_as!(
+        Payment,
+        r#"SELECT
+      id,
+      import_id,
+      row,
+      paid_at,
+      amount,
+      message,
+      payor_phone,
+      payor_name,
+      payee_phone,
+      payee_name,
+      order_id AS "order_id: OrderId",
+      status AS "status: PaymentStatus",
+      created_at
+    FROM payments
+    WHERE status IN ('unknown_order', 'amount_mismatch')
+    ORDER BY paid_at, row"#
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub struct NewAllocation<'a> {
+    pub payment_id: Option<Uuid>,
+    pub order_id: &'a OrderId,
+    pub amount: Decimal,
+    pub method: PaymentMethod,
+    pub note: Option<&'a str>,
+    pub created_by: Option<&'a str>,
+}
+
+pub async fn allocate(
+    executor: impl sqlx::PgExecutor<'_>,
+    allocation: NewAllocation<'_>,
+) -> sqlx::Result<Allocation> {
+    sqlx::query_as!(
+        Allocation,
+        r#"INSERT INTO payment_allocations (id, payment_id, order_id, amount, method, note, created_by)
+    VALUES ($1, $2, $3, $4, $5, $6, $7)
+    RETURNING
+      id,
+      payment_id,
+      order_id,
+      amount,
+      method AS "method: PaymentMethod",
+      note,
+      created_by,
+      created_at,
+      removed_by,
+      removed_at"#,
+        Uuid::new_v4(),
+        allocation.payment_id,
+        allocation.order_id.as_ref(),
+        allocation.amount,
+        allocation.method as PaymentMethod,
+        allocation.note,
+        allocation.created_by,
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Undo an allocation. Returns `None` if there is no such allocation or it
+/// has already been removed.
+pub async fn remove_allocation(
+    executor: impl sqlx::PgExecutor<'_>,
+    id: Uuid,
+    removed_by: &str,
+) -> sqlx::Result<Option<Allocation>> {
+    sqlx::query_as!(
+        Allocation,
+        r#"UPDATE payment_allocations SET removed_at = NOW(), removed_by = $2
+    WHERE id = $1 AND removed_at IS NULL
+    RETURNING
+      id,
+      payment_id,
+      order_id,
+      amount,
+      method AS "method: PaymentMethod",
+      note,
+      created_by,
+      created_at,
+      removed_by,
+      removed_at"#,
+        id,
+        removed_by,
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Sum of the allocations of a payment that have not been undone.
+pub async fn allocated_from_payment(
+    executor: impl sqlx::PgExecutor<'_>,
+    payment_id: Uuid,
+) -> sqlx::Result<Decimal> {
+    let sum = sqlx::query_scalar!(
+        "SELECT SUM(amount) FROM payment_allocations WHERE payment_id = $1 AND removed_at IS NULL",
+        payment_id,
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(sum.unwrap_or_default())
+}
+
+/// Sum of the allocations to an order that have not been undone.
+pub async fn allocated_to_order(
+    executor: impl sqlx::PgExecutor<'_>,
+    order_id: &OrderId,
+) -> sqlx::Result<Decimal> {
+    let sum = sqlx::query_scalar!(
+        "SELECT SUM(amount) FROM payment_allocations WHERE order_id = $1 AND removed_at IS NULL",
+        order_id.as_ref(),
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(sum.unwrap_or_default())
+}
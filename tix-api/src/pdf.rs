@@ -0,0 +1,182 @@
+//! Printable PDF tickets, one A4 page per ticket.
+
+use printpdf::{
+    BuiltinFont, Color, IndirectFontRef, Mm, PdfDocument, PdfLayerReference, Rect, Rgb,
+};
+use qrcode::QrResult;
+use time::macros::format_description;
+use time_tz::OffsetDateTimeExt;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    error::{Code, ResponseError},
+    event::Event,
+    locale::Locale,
+    order::Order,
+    qr,
+};
+
+const PAGE_WIDTH: Mm = Mm(210.0);
+const PAGE_HEIGHT: Mm = Mm(297.0);
+const MARGIN: f32 = 20.0;
+/// Width of the QR code on the page, including the quiet zone.
+const QR_SIZE: f32 = 100.0;
+
+pub struct TicketPage {
+    pub ticket_id: Uuid,
+    pub ticket_type: String,
+    /// Signed ticket code, rendered as a QR code.
+    pub code: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PdfError {
+    #[error(transparent)]
+    Pdf(#[from] printpdf::Error),
+    #[error(transparent)]
+    Qr(#[from] qrcode::types::QrError),
+}
+
+impl From<PdfError> for ResponseError {
+    fn from(e: PdfError) -> Self {
+        error!(?e);
+        Self::new(Code::InternalError, "pdf error")
+    }
+}
+
+/// Labels printed on the page, in the language of the order.
+struct Labels {
+    name: &'static str,
+    order: &'static str,
+}
+
+impl Labels {
+    const fn of(locale: Locale) -> Self {
+        match locale {
+            Locale::Sv => Self {
+                name: "Namn",
+                order: "Order",
+            },
+            Locale::En => Self {
+                name: "Name",
+                order: "Order",
+            },
+        }
+    }
+}
+
+fn draw_qr(layer: &PdfLayerReference, data: &str, x: f32, y: f32) -> QrResult<()> {
+    let code = qr::encode(data)?;
+    let modules = code.width();
+    // leave room for a quiet zone of four modules on each side
+    let module_size = QR_SIZE / (modules + 8) as f32;
+    let x = x + 4.0 * module_size;
+    let y = y + 4.0 * module_size;
+
+    layer.set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color != qrcode::Color::Dark {
+            continue;
+        }
+
+        let col = (i % modules) as f32;
+        // pdf coordinates grow upwards
+        let row = (modules - 1 - i / modules) as f32;
+        layer.add_rect(Rect::new(
+            Mm(x + col * module_size),
+            Mm(y + row * module_size),
+            Mm(x + (col + 1.0) * module_size),
+            Mm(y + (row + 1.0) * module_size),
+        ));
+    }
+
+    Ok(())
+}
+
+fn draw_page(
+    layer: &PdfLayerReference,
+    fonts: &(IndirectFontRef, IndirectFontRef),
+    event: &Event,
+    order: &Order,
+    ticket: &TicketPage,
+) -> QrResult<()> {
+    let (bold, regular) = fonts;
+    let labels = Labels::of(order.locale);
+    let starts_at = event
+        .starts_at
+        .to_timezone(time_tz::timezones::db::europe::STOCKHOLM)
+        .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+        .unwrap();
+
+    let mut y = PAGE_HEIGHT.0 - MARGIN - 10.0;
+    layer.use_text(&event.name, 28.0, Mm(MARGIN), Mm(y), bold);
+    y -= 12.0;
+    layer.use_text(
+        format!("{} · {}", event.venue, starts_at),
+        14.0,
+        Mm(MARGIN),
+        Mm(y),
+        regular,
+    );
+    y -= 8.0;
+    layer.use_text(&ticket.ticket_type, 14.0, Mm(MARGIN), Mm(y), regular);
+
+    y -= 16.0;
+    layer.use_text(
+        format!("{}: {}", labels.name, order.name.trim()),
+        12.0,
+        Mm(MARGIN),
+        Mm(y),
+        regular,
+    );
+    y -= 6.0;
+    layer.use_text(
+        format!("{}: {}", labels.order, order.id),
+        12.0,
+        Mm(MARGIN),
+        Mm(y),
+        regular,
+    );
+
+    y -= 10.0 + QR_SIZE;
+    draw_qr(layer, &ticket.code, (PAGE_WIDTH.0 - QR_SIZE) / 2.0, y)?;
+
+    layer.use_text(
+        ticket.ticket_id.to_string(),
+        8.0,
+        Mm(MARGIN),
+        Mm(MARGIN),
+        regular,
+    );
+
+    Ok(())
+}
+
+/// Render every ticket of `order` to a single PDF document.
+pub fn tickets(event: &Event, order: &Order, tickets: &[TicketPage]) -> Result<Vec<u8>, PdfError> {
+    let (doc, page, layer) = PdfDocument::new(
+        format!("{} – {}", event.name, order.id),
+        PAGE_WIDTH,
+        PAGE_HEIGHT,
+        "Ticket",
+    );
+    let fonts = (
+        doc.add_builtin_font(BuiltinFont::HelveticaBold)?,
+        doc.add_builtin_font(BuiltinFont::Helvetica)?,
+    );
+
+    let mut next = Some((page, layer));
+
+    for ticket in tickets {
+        let (page, layer) = next
+            .take()
+            .unwrap_or_else(|| doc.add_page(PAGE_WIDTH, PAGE_HEIGHT, "Ticket"));
+        let layer = doc.get_page(page).get_layer(layer);
+
+        draw_page(&layer, &fonts, event, order, ticket)?;
+    }
+
+    Ok(doc.save_to_bytes()?)
+}
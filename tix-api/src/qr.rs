@@ -0,0 +1,52 @@
+//! Server-side QR code rendering, so that every place showing a QR code
+//! (emails, PDFs, the frontend) uses the same image.
+
+use qrcode::{render::svg, Color, EcLevel, QrCode, QrResult};
+
+/// Pixels per QR module in rendered PNGs.
+const PNG_MODULE_SIZE: usize = 8;
+/// Width of the blank border around the code, in modules.
+const QUIET_ZONE: usize = 4;
+
+pub(crate) fn encode(data: &str) -> QrResult<QrCode> {
+    QrCode::with_error_correction_level(data, EcLevel::M)
+}
+
+pub fn svg(data: &str) -> QrResult<String> {
+    Ok(encode(data)?
+        .render::<svg::Color>()
+        .min_dimensions(256, 256)
+        .build())
+}
+
+/// Render `data` as an 8-bit grayscale PNG.
+pub fn png(data: &str) -> QrResult<Vec<u8>> {
+    let code = encode(data)?;
+    let modules = code.width();
+    let colors = code.to_colors();
+    let size = (modules + 2 * QUIET_ZONE) * PNG_MODULE_SIZE;
+
+    let mut pixels = vec![0xff; size * size];
+    for (i, color) in colors.iter().enumerate() {
+        if *color != Color::Dark {
+            continue;
+        }
+
+        let x0 = (i % modules + QUIET_ZONE) * PNG_MODULE_SIZE;
+        let y0 = (i / modules + QUIET_ZONE) * PNG_MODULE_SIZE;
+        for y in y0..y0 + PNG_MODULE_SIZE {
+            pixels[y * size + x0..y * size + x0 + PNG_MODULE_SIZE].fill(0);
+        }
+    }
+
+    let mut buf = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buf, size as u32, size as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    // writing to a Vec cannot fail and the dimensions always match
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&pixels).unwrap();
+    writer.finish().unwrap();
+
+    Ok(buf)
+}
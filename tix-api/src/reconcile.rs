@@ -0,0 +1,271 @@
+//! Matching of imported payments against orders.
+//!
+//! Every payment is classified on its own, so that payments that cannot be
+//! matched automatically show up in the report instead of being dropped.
+
+use std::collections::{HashMap, HashSet};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+use sqlx::PgConnection;
+use time::OffsetDateTime;
+
+use crate::{
+    history::OrderEventKind,
+    order::{self, Change, OrderError, OrderId, OrderStatus, ORDER_ID_LEN},
+    payment::{self, NewAllocation, Payment, PaymentMethod, PaymentStatus},
+    routes::auth::Identity,
+};
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Outcome {
+    /// The order is paid by this transaction.
+    Matched { order_id: OrderId },
+    /// The message matches an order but the amount does not.
+    AmountMismatch { order_id: OrderId, expected: i32 },
+    /// No order could be found in the message.
+    UnknownOrder,
+    /// An earlier payment in the same batch already paid the order.
+    Duplicate { order_id: OrderId },
+    /// The order was paid before this batch was matched.
+    AlreadyPaid { order_id: OrderId },
+    /// The order expired or was canceled before it was paid. The payment is
+    /// allocated to it but the order is left as is, so the money shows up as
+    /// due for a [refund](crate::refund) unless the order is reopened.
+    OrderClosed {
+        order_id: OrderId,
+        order_status: OrderStatus,
+    },
+    /// Money paid out of the account, e.g. a refund or a bank fee, which is
+    /// not matched to any order.
+    Outgoing,
+}
+
+impl Outcome {
+    pub fn status(&self) -> PaymentStatus {
+        match self {
+            Self::Matched { .. } => PaymentStatus::Matched,
+            Self::AmountMismatch { .. } => PaymentStatus::AmountMismatch,
+            Self::UnknownOrder => PaymentStatus::UnknownOrder,
+            Self::Duplicate { .. } => PaymentStatus::Duplicate,
+            Self::AlreadyPaid { .. } => PaymentStatus::AlreadyPaid,
+            Self::OrderClosed { .. } => PaymentStatus::OrderClosed,
+            Self::Outgoing => PaymentStatus::Outgoing,
+        }
+    }
+
+    pub fn order_id(&self) -> Option<&OrderId> {
+        match self {
+            Self::Matched { order_id }
+            | Self::AmountMismatch { order_id, .. }
+            | Self::Duplicate { order_id }
+            | Self::AlreadyPaid { order_id }
+            | Self::OrderClosed { order_id, .. } => Some(order_id),
+            Self::UnknownOrder | Self::Outgoing => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportRow {
+    pub payment: Payment,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct Summary {
+    pub matched: usize,
+    pub amount_mismatch: usize,
+    pub unknown_order: usize,
+    pub duplicate: usize,
+    pub already_paid: usize,
+    pub order_closed: usize,
+    pub outgoing: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub summary: Summary,
+    pub rows: Vec<ReportRow>,
+}
+
+/// Order ids that `message` might refer to, most likely first. People write
+/// all sorts of things around the id and sometimes split it in two, so any
+/// run of words adding up to the length of an id is considered.
+pub fn candidates(message: &str) -> Vec<OrderId> {
+    let words = message
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>();
+    let mut candidates = Vec::new();
+
+    for start in 0..words.len() {
+        let mut joined = String::new();
+
+        for word in &words[start..] {
+            joined.push_str(word);
+
+            if let Some(id) = OrderId::normalize(&joined) {
+                if !candidates.contains(&id) {
+                    candidates.push(id);
+                }
+                break;
+            }
+            if joined.chars().count() >= ORDER_ID_LEN {
+                break;
+            }
+        }
+    }
+
+    candidates
+}
+
+struct OrderRow {
+    amount: i32,
+    paid_at: Option<OffsetDateTime>,
+    status: OrderStatus,
+}
+
+/// Classify every payment. Nothing is written; see [`apply`].
+pub async fn reconcile(
+    executor: impl sqlx::PgExecutor<'_>,
+    payments: Vec<Payment>,
+) -> sqlx::Result<Report> {
+    let candidates = payments
+        .iter()
+        .map(|p| candidates(&p.message))
+        .collect::<Vec<_>>();
+    let ids = candidates
+        .iter()
+        .flatten()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+    let orders = sqlx::query!(
+        r#"SELECT id, amount, paid_at, status AS "status: OrderStatus" FROM orders WHERE id = ANY($1)"#,
+        &ids
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(|r| {
+        (
+            OrderId::from(r.id),
+            OrderRow {
+                amount: r.amount,
+                paid_at: r.paid_at,
+                status: r.status,
+            },
+        )
+    })
+    .collect::<HashMap<_, _>>();
+
+    let mut paid = HashSet::new();
+    let mut summary = Summary::default();
+    let mut rows = Vec::with_capacity(payments.len());
+
+    for (mut payment, candidates) in payments.into_iter().zip(candidates) {
+        let found = candidates
+            .into_iter()
+            .find_map(|id| orders.get(&id).map(|order| (id, order)));
+
+        let outcome = match found {
+            _ if payment.amount < Decimal::ZERO => Outcome::Outgoing,
+            None => Outcome::UnknownOrder,
+            Some((order_id, order)) if order.paid_at.is_some() => Outcome::AlreadyPaid { order_id },
+            Some((order_id, order))
+                if matches!(order.status, OrderStatus::Expired | OrderStatus::Canceled) =>
+            {
+                Outcome::OrderClosed {
+                    order_id,
+                    order_status: order.status,
+                }
+            }
+            Some((order_id, _)) if paid.contains(&order_id) => Outcome::Duplicate { order_id },
+            Some((order_id, order)) if payment.amount != Decimal::from(order.amount) => {
+                Outcome::AmountMismatch {
+                    order_id,
+                    expected: order.amount,
+                }
+            }
+            Some((order_id, _)) => {
+                paid.insert(order_id.clone());
+                Outcome::Matched { order_id }
+            }
+        };
+
+        match outcome {
+            Outcome::Matched { .. } => summary.matched += 1,
+            Outcome::AmountMismatch { .. } => summary.amount_mismatch += 1,
+            Outcome::UnknownOrder => summary.unknown_order += 1,
+            Outcome::Duplicate { .. } => summary.duplicate += 1,
+            Outcome::AlreadyPaid { .. } => summary.already_paid += 1,
+            Outcome::OrderClosed { .. } => summary.order_closed += 1,
+            Outcome::Outgoing => summary.outgoing += 1,
+        }
+
+        payment.status = outcome.status();
+        payment.order_id = outcome.order_id().cloned();
+        rows.push(ReportRow { payment, outcome });
+    }
+
+    Ok(Report { summary, rows })
+}
+
+/// Store how every payment was matched and mark the orders of matched
+/// payments as paid at the time of the payment. Payments for closed orders
+/// are allocated to them without changing their status, so that what was
+/// paid is due as a [refund](crate::refund). Every allocation is recorded
+/// in the history of its order as made by `actor`.
+pub async fn apply(
+    conn: &mut PgConnection,
+    report: &Report,
+    actor: Option<&Identity>,
+) -> Result<(), OrderError> {
+    for row in &report.rows {
+        sqlx::query!(
+            "UPDATE payments SET status = $2, order_id = $3 WHERE id = $1",
+            row.payment.id,
+            row.payment.status as PaymentStatus,
+            row.payment.order_id.as_ref().map(AsRef::as_ref),
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    for row in &report.rows {
+        let (Outcome::Matched { order_id } | Outcome::OrderClosed { order_id, .. }) = &row.outcome
+        else {
+            continue;
+        };
+
+        let Some(order) = order::lock(&mut *conn, order_id).await? else {
+            continue;
+        };
+        payment::allocate(
+            &mut *conn,
+            NewAllocation {
+                payment_id: Some(row.payment.id),
+                order_id,
+                amount: row.payment.amount,
+                method: PaymentMethod::Swish,
+                note: None,
+                created_by: None,
+            },
+        )
+        .await?;
+
+        // closed orders are left as they are
+        order::refresh_paid(
+            &mut *conn,
+            &order,
+            row.payment.paid_at,
+            Change::new(OrderEventKind::PaymentAdded, actor),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
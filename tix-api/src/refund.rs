@@ -0,0 +1,103 @@
+//! Money paid back to customers, e.g. for canceled tickets.
+//!
+//! An order is due a refund when its [allocations](crate::payment::Allocation)
+//! add up to more than it still costs, which is nothing once the order has
+//! been canceled. Refunds are made outside of the system and recorded here by
+//! the admin who made them.
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    order::{Order, OrderId},
+    payment::{self, PaymentMethod},
+};
+
+#[derive(Debug, Serialize)]
+pub struct Refund {
+    pub id: Uuid,
+    pub order_id: OrderId,
+    pub amount: Decimal,
+    pub method: PaymentMethod,
+    /// E.g. the Swish payment reference.
+    pub reference: Option<String>,
+    pub note: Option<String>,
+    pub approved_by: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+pub struct NewRefund<'a> {
+    pub order_id: &'a OrderId,
+    pub amount: Decimal,
+    pub method: PaymentMethod,
+    pub reference: Option<&'a str>,
+    pub note: Option<&'a str>,
+    pub approved_by: &'a str,
+}
+
+pub async fn record(
+    executor: impl sqlx::PgExecutor<'_>,
+    refund: NewRefund<'_>,
+) -> sqlx::Result<Refund> {
+    sqlx::query_as!(
+        Refund,
+        r#"INSERT INTO refunds (id, order_id, amount, method, reference, note, approved_by)
+    VALUES ($1, $2, $3, $4, $5, $6, $7)
+    RETURNING
+      id,
+      order_id,
+      amount,
+      method AS "method: PaymentMethod",
+      reference,
+      note,
+      approved_by,
+      created_at"#,
+        Uuid::new_v4(),
+        refund.order_id.as_ref(),
+        refund.amount,
+        refund.method as PaymentMethod,
+        refund.reference,
+        refund.note,
+        refund.approved_by,
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Sum of the refunds of an order.
+pub async fn refunded_to_order(
+    executor: impl sqlx::PgExecutor<'_>,
+    order_id: &OrderId,
+) -> sqlx::Result<Decimal> {
+    let sum = sqlx::query_scalar!(
+        "SELECT SUM(amount) FROM refunds WHERE order_id = $1",
+        order_id.as_ref(),
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(sum.unwrap_or_default())
+}
+
+/// What is left to refund of an order that has been `paid` and `refunded`
+/// these amounts.
+pub fn due(order: &Order, paid: Decimal, refunded: Decimal) -> Decimal {
+    let cost = if order.status.is_canceled() {
+        Decimal::ZERO
+    } else {
+        order.amount.into()
+    };
+
+    (paid - refunded - cost).max(Decimal::ZERO)
+}
+
+/// What is left to refund of `order`, see [`due`].
+pub async fn due_for_order(conn: &mut sqlx::PgConnection, order: &Order) -> sqlx::Result<Decimal> {
+    let paid = payment::allocated_to_order(&mut *conn, &order.id).await?;
+    let refunded = refunded_to_order(&mut *conn, &order.id).await?;
+
+    Ok(due(order, paid, refunded))
+}
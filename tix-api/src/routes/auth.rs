@@ -12,13 +12,33 @@ use axum_extra::extract::{
 use openidconnect::{core::CoreIdToken, Nonce};
 use serde::{Deserialize, Serialize};
 
-use crate::error::{Code, ResponseError, Result};
+use crate::{
+    error::{Code, ResponseError, Result},
+    oidc::LocalizedClaimExt,
+    staff::{self, Role},
+};
 
 use super::AppState;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Identity {
     pub email: String,
+    pub role: Role,
+}
+
+impl Identity {
+    /// Fail with [`Code::Forbidden`] unless the identity has one of `roles`.
+    pub fn require(&self, roles: &[Role]) -> Result<()> {
+        if roles.contains(&self.role) {
+            Ok(())
+        } else {
+            Err(ResponseError::new(Code::Forbidden, "insufficient role"))
+        }
+    }
+}
+
+fn not_staff() -> ResponseError {
+    ResponseError::new(Code::Forbidden, "not a staff member")
 }
 
 #[axum::async_trait]
@@ -30,7 +50,8 @@ where
     type Rejection = ResponseError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let jar = PrivateCookieJar::<Key>::from_request_parts(parts, &AppState::from_ref(state))
+        let state = AppState::from_ref(state);
+        let jar = PrivateCookieJar::<Key>::from_request_parts(parts, &state)
             .await
             .unwrap();
 
@@ -39,8 +60,15 @@ where
             "missing auth cookie",
         ))?;
 
+        // look the role up on every request so that revoking access takes
+        // effect immediately
+        let staff = staff::get(&state.pool, cookie.value())
+            .await?
+            .ok_or_else(not_staff)?;
+
         Ok(Identity {
-            email: cookie.value().into(),
+            email: staff.email,
+            role: staff.role,
         })
     }
 }
@@ -68,14 +96,36 @@ async fn login(
         .into_claims(&client.id_token_verifier(), &nonce)
         .map_err(|e| ResponseError::new(Code::InvalidIdToken, e.to_string()))?;
 
+    if claims.email_verified() != Some(true) {
+        return Err(ResponseError::new(
+            Code::InvalidIdToken,
+            "email not verified",
+        ));
+    }
+
     let email = claims.email().unwrap().to_string();
-    let cookie = Cookie::build(("email", email.clone()))
+    let staff = staff::get(&state.pool, &email)
+        .await?
+        .ok_or_else(not_staff)?;
+
+    sqlx::query!(
+        "UPDATE staff SET name = $1 WHERE email = $2",
+        claims.name().default_locale(),
+        staff.email,
+    )
+    .execute(&state.pool)
+    .await?;
+
+    let cookie = Cookie::build(("email", staff.email.clone()))
         .http_only(true)
         .path("/")
         .permanent()
         .same_site(SameSite::None)
         .build();
-    let identity = Identity { email };
+    let identity = Identity {
+        email: staff.email,
+        role: staff.role,
+    };
 
     Ok((jar.add(cookie), Json(identity)))
 }
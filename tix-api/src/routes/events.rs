@@ -0,0 +1,483 @@
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    email::{self, EmailError, EmailKind, EmailTemplate},
+    error::{Code, ResponseError, Result},
+    event::{self, Event, EventError, TicketType},
+    locale::Locale,
+    order::{self, OrderId},
+    staff::Role,
+    waitlist::{self, WaitlistError},
+};
+
+use super::{
+    auth::Identity,
+    tickets::{tickets_remaining, TicketsRemaining},
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+struct EventRequest {
+    name: String,
+    venue: String,
+    #[serde(with = "time::serde::rfc3339")]
+    starts_at: OffsetDateTime,
+    capacity: u16,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    sales_start: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    sales_end: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TicketTypeRequest {
+    name: String,
+    price: u16,
+    quota: u16,
+    #[serde(default = "default_public")]
+    public: bool,
+}
+
+const fn default_public() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize)]
+struct DetailedEvent {
+    #[serde(flatten)]
+    event: Event,
+    ticket_types: Vec<TicketType>,
+    tickets_remaining: TicketsRemaining,
+}
+
+async fn list_events(state: AppState) -> Result<impl IntoResponse> {
+    let events = sqlx::query_as!(Event, "SELECT * FROM events ORDER BY starts_at ASC")
+        .fetch_all(&state.pool)
+        .await?;
+
+    Ok(Json(events))
+}
+
+async fn create_event(
+    state: AppState,
+    identity: Identity,
+    Json(req): Json<EventRequest>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let event = sqlx::query_as!(
+        Event,
+        "INSERT INTO events (id, name, venue, starts_at, capacity, sales_start, sales_end)
+    VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
+        Uuid::new_v4(),
+        req.name,
+        req.venue,
+        req.starts_at,
+        i32::from(req.capacity),
+        req.sales_start,
+        req.sales_end,
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(event)))
+}
+
+/// Ticket types visible to the caller. Only admins see non-public types.
+async fn visible_ticket_types(
+    state: &AppState,
+    event: &Event,
+    identity: Option<&Identity>,
+) -> Result<Vec<TicketType>> {
+    let is_admin = identity.is_some_and(|i| i.require(&[Role::Admin]).is_ok());
+    let ticket_types = event::ticket_types(&state.pool, event.id)
+        .await?
+        .into_iter()
+        .filter(|t| t.public || is_admin)
+        .collect();
+
+    Ok(ticket_types)
+}
+
+async fn get_event(
+    event: Event,
+    state: AppState,
+    identity: Option<Identity>,
+) -> Result<impl IntoResponse> {
+    let ticket_types = visible_ticket_types(&state, &event, identity.as_ref()).await?;
+    let tickets_remaining = tickets_remaining(&state.pool, &event).await?;
+
+    Ok(Json(DetailedEvent {
+        event,
+        ticket_types,
+        tickets_remaining,
+    }))
+}
+
+async fn update_event(
+    event: Event,
+    state: AppState,
+    identity: Identity,
+    Json(req): Json<EventRequest>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let event = sqlx::query_as!(
+        Event,
+        "UPDATE events
+    SET name = $2, venue = $3, starts_at = $4, capacity = $5, sales_start = $6, sales_end = $7
+    WHERE id = $1 RETURNING *",
+        event.id,
+        req.name,
+        req.venue,
+        req.starts_at,
+        i32::from(req.capacity),
+        req.sales_start,
+        req.sales_end,
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    Ok(Json(event))
+}
+
+async fn delete_event(
+    event: Event,
+    state: AppState,
+    identity: Identity,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let has_orders = sqlx::query_scalar!(
+        "SELECT EXISTS (SELECT 1 FROM orders WHERE event_id = $1)",
+        event.id
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .unwrap_or(false);
+
+    if has_orders {
+        return Err(ResponseError::new(
+            Code::EventHasOrders,
+            "event has orders and cannot be deleted",
+        ));
+    }
+
+    sqlx::query!("DELETE FROM events WHERE id = $1", event.id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_tickets_remaining(event: Event, state: AppState) -> Result<impl IntoResponse> {
+    let remaining = tickets_remaining(&state.pool, &event).await?;
+
+    Ok(Json(remaining))
+}
+
+async fn list_ticket_types(
+    event: Event,
+    state: AppState,
+    identity: Option<Identity>,
+) -> Result<impl IntoResponse> {
+    let ticket_types = visible_ticket_types(&state, &event, identity.as_ref()).await?;
+
+    Ok(Json(ticket_types))
+}
+
+async fn create_ticket_type(
+    event: Event,
+    state: AppState,
+    identity: Identity,
+    Json(req): Json<TicketTypeRequest>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let ticket_type = sqlx::query_as!(
+        TicketType,
+        "INSERT INTO ticket_types (id, event_id, name, price, quota, public)
+    VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+        Uuid::new_v4(),
+        event.id,
+        req.name,
+        i32::from(req.price),
+        i32::from(req.quota),
+        req.public,
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(ticket_type)))
+}
+
+async fn update_ticket_type(
+    state: AppState,
+    identity: Identity,
+    Path((event_id, ticket_type_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<TicketTypeRequest>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let ticket_type = sqlx::query_as!(
+        TicketType,
+        "UPDATE ticket_types SET name = $3, price = $4, quota = $5, public = $6
+    WHERE id = $1 AND event_id = $2 RETURNING *",
+        ticket_type_id,
+        event_id,
+        req.name,
+        i32::from(req.price),
+        i32::from(req.quota),
+        req.public,
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(EventError::TicketTypeNotFound)?;
+
+    Ok(Json(ticket_type))
+}
+
+async fn delete_ticket_type(
+    state: AppState,
+    identity: Identity,
+    Path((event_id, ticket_type_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let has_tickets = sqlx::query_scalar!(
+        "SELECT EXISTS (SELECT 1 FROM tickets WHERE ticket_type_id = $1)",
+        ticket_type_id
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .unwrap_or(false);
+
+    if has_tickets {
+        return Err(ResponseError::new(
+            Code::TicketTypeHasTickets,
+            "ticket type has tickets and cannot be deleted",
+        ));
+    }
+
+    let res = sqlx::query!(
+        "DELETE FROM ticket_types WHERE id = $1 AND event_id = $2",
+        ticket_type_id,
+        event_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        return Err(EventError::TicketTypeNotFound.into());
+    }
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+struct LocaleQuery {
+    #[serde(default)]
+    locale: Locale,
+}
+
+#[derive(Debug, Serialize)]
+struct EventEmailTemplate {
+    kind: EmailKind,
+    locale: Locale,
+    #[serde(flatten)]
+    template: EmailTemplate,
+    /// Whether the event overrides the default template.
+    custom: bool,
+}
+
+async fn get_email_template(
+    event: Event,
+    state: AppState,
+    identity: Identity,
+    Path((_, kind)): Path<(Uuid, EmailKind)>,
+    Query(LocaleQuery { locale }): Query<LocaleQuery>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let custom = sqlx::query_as!(
+        EmailTemplate,
+        "SELECT subject, text, html FROM email_templates WHERE event_id = $1 AND kind = $2 AND locale = $3",
+        event.id,
+        kind as EmailKind,
+        locale.as_str(),
+    )
+    .fetch_optional(&state.pool)
+    .await?;
+
+    Ok(Json(EventEmailTemplate {
+        kind,
+        locale,
+        custom: custom.is_some(),
+        template: custom.unwrap_or_else(|| EmailTemplate::default_for(kind, locale)),
+    }))
+}
+
+async fn put_email_template(
+    event: Event,
+    state: AppState,
+    identity: Identity,
+    Path((_, kind)): Path<(Uuid, EmailKind)>,
+    Query(LocaleQuery { locale }): Query<LocaleQuery>,
+    Json(template): Json<EmailTemplate>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+    template.validate().map_err(EmailError::from)?;
+
+    let template = sqlx::query_as!(
+        EmailTemplate,
+        "INSERT INTO email_templates (event_id, kind, locale, subject, text, html) VALUES ($1, $2, $3, $4, $5, $6)
+    ON CONFLICT (event_id, kind, locale) DO UPDATE
+    SET subject = EXCLUDED.subject, text = EXCLUDED.text, html = EXCLUDED.html, updated_at = NOW()
+    RETURNING subject, text, html",
+        event.id,
+        kind as EmailKind,
+        locale.as_str(),
+        template.subject,
+        template.text,
+        template.html,
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    Ok(Json(EventEmailTemplate {
+        kind,
+        locale,
+        template,
+        custom: true,
+    }))
+}
+
+/// Go back to the default template.
+async fn delete_email_template(
+    event: Event,
+    state: AppState,
+    identity: Identity,
+    Path((_, kind)): Path<(Uuid, EmailKind)>,
+    Query(LocaleQuery { locale }): Query<LocaleQuery>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    sqlx::query!(
+        "DELETE FROM email_templates WHERE event_id = $1 AND kind = $2 AND locale = $3",
+        event.id,
+        kind as EmailKind,
+        locale.as_str(),
+    )
+    .execute(&state.pool)
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+struct PreviewRequest {
+    order_id: Option<OrderId>,
+    /// Waitlist offers are not about an order, so they are previewed for a
+    /// waitlist entry instead.
+    waitlist_id: Option<Uuid>,
+    /// Render this instead of the stored template, e.g. to try out changes
+    /// before saving them.
+    template: Option<EmailTemplate>,
+}
+
+/// Render an email for one of the event's orders, or waitlist entries, without
+/// sending it, in their locale.
+async fn preview_email_template(
+    event: Event,
+    state: AppState,
+    identity: Identity,
+    Path((_, kind)): Path<(Uuid, EmailKind)>,
+    Json(req): Json<PreviewRequest>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    if kind == EmailKind::WaitlistOffer {
+        let entry = match req.waitlist_id {
+            Some(id) => waitlist::get(&state.pool, id).await?,
+            None => None,
+        }
+        .filter(|entry| entry.event_id == event.id)
+        .ok_or(WaitlistError::NotFound)?;
+        let ticket_type = event::ticket_types(&state.pool, event.id)
+            .await?
+            .into_iter()
+            .find(|t| t.id == entry.ticket_type_id)
+            .map(|t| t.name)
+            .unwrap_or_default();
+
+        let template = match req.template {
+            Some(template) => template,
+            None => email::template(&state.pool, event.id, kind, entry.locale).await?,
+        };
+        let context = email::WaitlistContext::new(&state.email, &event, &ticket_type, &entry);
+        let rendered = template.render(&context).map_err(EmailError::from)?;
+
+        return Ok(Json(rendered));
+    }
+
+    let order = match &req.order_id {
+        Some(id) => order::get(&state.pool, id).await?,
+        None => None,
+    }
+    .filter(|order| order.event_id == event.id)
+    .ok_or_else(|| ResponseError::new(Code::OrderNotFound, "order not found"))?;
+
+    let template = match req.template {
+        Some(template) => template,
+        None => email::template(&state.pool, event.id, kind, order.locale).await?,
+    };
+    let context = email::Context::new(&state.email, &event, &order, state.payment_deadline);
+    let rendered = template.render(&context).map_err(EmailError::from)?;
+
+    Ok(Json(rendered))
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::<AppState>::new()
+        .route("/", get(list_events).post(create_event))
+        .route(
+            "/:event_id",
+            get(get_event).put(update_event).delete(delete_event),
+        )
+        .route("/:event_id/remaining", get(get_tickets_remaining))
+        .route(
+            "/:event_id/ticket-types",
+            get(list_ticket_types).post(create_ticket_type),
+        )
+        .route(
+            "/:event_id/ticket-types/:ticket_type_id",
+            put(update_ticket_type).delete(delete_ticket_type),
+        )
+        .route(
+            "/:event_id/email-templates/:kind",
+            get(get_email_template)
+                .put(put_email_template)
+                .delete(delete_email_template),
+        )
+        .route(
+            "/:event_id/email-templates/:kind/preview",
+            post(preview_email_template),
+        )
+}
@@ -1,4 +1,4 @@
-use std::{convert::Infallible, sync::Arc};
+use std::{convert::Infallible, num::NonZeroUsize, sync::Arc};
 
 use axum::{
     extract::{FromRef, FromRequestParts},
@@ -8,15 +8,26 @@ use axum::{
 use axum_extra::extract::cookie::Key;
 
 pub mod auth;
+pub mod events;
 pub mod orders;
+pub mod outbox;
+pub mod payments;
+pub mod staff;
 pub mod tickets;
+pub mod waitlist;
 
 #[derive(Clone)]
 pub struct AppState {
     pub pool: sqlx::PgPool,
-    pub smtp: lettre::AsyncSmtpTransport<lettre::Tokio1Executor>,
     pub oidc: Arc<crate::oidc::Oidc>,
     pub cookie_key: Key,
+    /// How long an order may stay unpaid before it is canceled.
+    pub payment_deadline: time::Duration,
+    pub ticket_signer: Arc<crate::ticket_code::TicketSigner>,
+    pub email: Arc<crate::email::EmailConfig>,
+    pub max_tickets_per_order: NonZeroUsize,
+    /// Set if payment requests are made through the Swish Commerce API.
+    pub swish: Option<Arc<crate::swish::commerce::Client>>,
 }
 
 impl FromRef<AppState> for Key {
@@ -41,8 +52,13 @@ where
 pub fn routes() -> Router<AppState> {
     Router::<AppState>::new()
         .nest("/auth", auth::routes())
+        .nest("/events", events::routes())
         .nest("/orders", orders::routes())
+        .nest("/outbox", outbox::routes())
+        .nest("/payments", payments::routes())
+        .nest("/staff", staff::routes())
         .nest("/tickets", tickets::routes())
+        .nest("/waitlist", waitlist::routes())
         .layer(tower_http::cors::CorsLayer::very_permissive())
         .layer(tower_http::trace::TraceLayer::new_for_http())
 }
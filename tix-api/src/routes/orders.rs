@@ -1,24 +1,36 @@
-use std::collections::HashMap;
-use std::{convert::TryInto, num::NonZeroUsize};
+use std::collections::{BTreeMap, HashMap};
+use std::{convert::TryInto, iter, num::NonZeroUsize};
 
-use axum::http::StatusCode;
+use axum::extract::Query;
+use axum::http::{header, StatusCode};
 use axum::response::IntoResponse;
-use axum::routing::{get, post};
+use axum::routing::{delete, get, post};
 use axum::{Json, Router};
-use lettre::message::Mailbox;
-use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use sqlx::PgExecutor;
 use sqlx::{postgres::PgQueryResult, types::Uuid};
+use sqlx::{PgConnection, PgExecutor};
 use time::OffsetDateTime;
+use tracing::error;
 
-use crate::email::{send_order_confirmation, send_tickets};
+use crate::email::{self, EmailKind};
 use crate::error::{Code, ResponseError, Result};
-use crate::order::{Order, OrderId};
-use crate::swish;
+use crate::event::{self, Event, EventError};
+use crate::history::{self, OrderEventKind};
+use crate::locale::Locale;
+use crate::order::{self, Change, Order, OrderId, OrderStatus};
+use crate::outbox;
+use crate::payment;
+use crate::pdf::{self, TicketPage};
+use crate::qr;
+use crate::reconcile;
+use crate::refund;
+use crate::staff::Role;
+use crate::swish::{self, commerce, request::PaymentRequest};
+use crate::ticket_code::TicketClaims;
 
 use super::auth::Identity;
-use super::tickets::tickets_remaining;
+use super::tickets::{self, tickets_remaining};
 use super::AppState;
 
 #[derive(Debug, Serialize)]
@@ -27,6 +39,19 @@ pub struct Ticket {
     pub order_id: OrderId,
     #[serde(with = "time::serde::rfc3339::option")]
     pub scanned_at: Option<OffsetDateTime>,
+    pub ticket_type_id: Uuid,
+    /// What the ticket cost when it was ordered.
+    pub price: i32,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub canceled_at: Option<OffsetDateTime>,
+    /// Unset when canceled by the customer.
+    pub canceled_by: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TicketRequest {
+    ticket_type_id: Uuid,
+    count: NonZeroUsize,
 }
 
 #[derive(Debug, Deserialize)]
@@ -34,22 +59,28 @@ struct CreateOrder {
     email: String,
     name: String,
     phone: String,
-    count: NonZeroUsize,
+    event_id: Uuid,
+    tickets: Vec<TicketRequest>,
+    #[serde(default)]
+    locale: Locale,
 }
 
+/// Insert one ticket per element of `tickets`, given as ticket type and
+/// price.
 async fn insert_tickets(
     executor: impl sqlx::PgExecutor<'_>,
     order_id: OrderId,
-    count: usize,
+    tickets: &[(Uuid, i32)],
 ) -> sqlx::Result<PgQueryResult> {
-    let mut query_builder = sqlx::QueryBuilder::new("INSERT INTO tickets (id, order_id) ");
+    let mut query_builder =
+        sqlx::QueryBuilder::new("INSERT INTO tickets (id, order_id, ticket_type_id, price) ");
 
-    query_builder.push_values(
-        std::iter::repeat_with(Uuid::new_v4).take(count),
-        |mut b, ticket_id| {
-            b.push_bind(ticket_id).push_bind(&order_id);
-        },
-    );
+    query_builder.push_values(tickets, |mut b, (ticket_type_id, price)| {
+        b.push_bind(Uuid::new_v4())
+            .push_bind(&order_id)
+            .push_bind(ticket_type_id)
+            .push_bind(price);
+    });
 
     let query = query_builder.build();
 
@@ -60,6 +91,8 @@ async fn insert_tickets(
 pub enum CreateOrderError {
     #[error("too many tickets requested")]
     TooManyTickets,
+    #[error("no tickets requested")]
+    NoTickets,
 }
 
 impl From<CreateOrderError> for ResponseError {
@@ -68,46 +101,215 @@ impl From<CreateOrderError> for ResponseError {
             CreateOrderError::TooManyTickets => {
                 Self::new(Code::TooManyTickets, "too many tickets requested")
             }
+            CreateOrderError::NoTickets => {
+                Self::new(Code::NoTicketsRequested, "no tickets requested")
+            }
         }
     }
 }
 
-async fn create_order(state: AppState, Json(req): Json<CreateOrder>) -> Result<impl IntoResponse> {
+/// Who an order is for.
+pub(super) struct Customer<'a> {
+    pub email: &'a str,
+    pub name: &'a str,
+    pub phone: &'a str,
+    pub locale: Locale,
+}
+
+/// Insert an order of `tickets`, given as ticket type and price, and queue
+/// its confirmation. Whether the tickets are available is up to the caller,
+/// which should hold the [event lock](event::lock). Free orders are paid
+/// right away.
+pub(super) async fn insert_order(
+    conn: &mut PgConnection,
+    state: &AppState,
+    event: &Event,
+    customer: Customer<'_>,
+    tickets: &[(Uuid, i32)],
+    identity: Option<&Identity>,
+) -> Result<Order> {
+    let order_id = OrderId::new();
+    let amount = tickets.iter().map(|(_, price)| price).sum::<i32>();
+
+    let order = sqlx::query_as!(
+        Order,
+        r#"INSERT INTO orders (id, email, name, phone, amount, event_id, locale) VALUES ($1, $2, $3, $4, $5, $6, $7)
+    RETURNING
+      id,
+      email,
+      name,
+      phone,
+      amount,
+      created_at,
+      checked_out_at,
+      paid_at,
+      canceled_at,
+      emailed_at,
+      event_id,
+      reminded_at,
+      status AS "status: OrderStatus",
+      locale"#,
+        order_id.as_ref(),
+        customer.email,
+        customer.name,
+        customer.phone,
+        amount,
+        event.id,
+        customer.locale.as_str(),
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    insert_tickets(&mut *conn, order_id, tickets).await?;
+    order::record(
+        &mut *conn,
+        &order,
+        Change::new(OrderEventKind::Created, identity),
+    )
+    .await?;
+
+    // nothing to pay for free tickets, so they can be sent right away
+    let order = if amount == 0 {
+        order::refresh_paid(
+            &mut *conn,
+            &order,
+            order.created_at,
+            Change::new(OrderEventKind::PaymentAdded, identity),
+        )
+        .await?
+    } else {
+        order
+    };
+
+    let message = email::compose(
+        &mut *conn,
+        &state.email,
+        EmailKind::OrderConfirmation,
+        event,
+        &order,
+        state.payment_deadline,
+        None,
+    )
+    .await?;
+    outbox::enqueue(
+        &mut *conn,
+        EmailKind::OrderConfirmation.as_str(),
+        Some(&order.id),
+        &message,
+    )
+    .await?;
+
+    Ok(order)
+}
+
+/// Cancel the Swish payment requests of a canceled `order` in the
+/// background. Those that are missed are canceled when they are next polled.
+pub(super) fn cancel_payment_requests(state: &AppState, order: &Order) {
+    if let Some(client) = state.swish.clone() {
+        let pool = state.pool.clone();
+        let order_id = order.id.clone();
+        tokio::spawn(async move {
+            if let Err(err) = commerce::cancel_for_order(&pool, &client, &order_id).await {
+                error!(%order_id, "cannot cancel swish payment requests: {err}");
+            }
+        });
+    }
+}
+
+/// Ask Swish for a payment of a newly created `order` in the background, if
+/// payments are requested through the API and there is anything to pay.
+pub(super) fn request_payment(state: &AppState, order: &Order) {
+    if order.status != OrderStatus::Reserved {
+        return;
+    }
+
+    if let Some(client) = state.swish.clone() {
+        let pool = state.pool.clone();
+        let order = order.clone();
+        tokio::spawn(async move {
+            if let Err(err) = commerce::request_payment(&pool, &client, &order).await {
+                error!(order_id = %order.id, "cannot request swish payment: {err}");
+            }
+        });
+    }
+}
+
+async fn create_order(
+    state: AppState,
+    identity: Option<Identity>,
+    Json(req): Json<CreateOrder>,
+) -> Result<impl IntoResponse> {
     let mut tx = state.pool.begin().await?;
     let CreateOrder {
         email,
         name,
         phone,
-        count: tickets,
+        event_id,
+        tickets,
+        locale,
     } = req;
+    let is_admin = identity
+        .as_ref()
+        .is_some_and(|i| i.require(&[Role::Admin]).is_ok());
+
+    // held until the order is committed, so that what is remaining cannot
+    // change in between
+    let event = event::lock(&mut *tx, event_id).await?;
+
+    if !event.is_on_sale(OffsetDateTime::now_utc()) {
+        return Err(EventError::SalesClosed.into());
+    }
+
+    // merge duplicate ticket types so that quotas are checked correctly
+    let mut counts = BTreeMap::<Uuid, usize>::new();
+    for req in &tickets {
+        *counts.entry(req.ticket_type_id).or_default() += req.count.get();
+    }
 
-    let mbox = Mailbox::new(Some(name.clone()), email.parse().unwrap());
+    let total: usize = counts.values().sum();
+    let remaining = tickets_remaining(&mut *tx, &event).await?;
 
-    if tickets.get() > 10 || tickets.get() > tickets_remaining(&mut *tx).await?.try_into().unwrap()
-    {
+    if total == 0 {
+        return Err(CreateOrderError::NoTickets.into());
+    }
+
+    if total > state.max_tickets_per_order.get() || total > remaining.total.try_into().unwrap() {
         return Err(CreateOrderError::TooManyTickets.into());
     }
 
-    let order_id = OrderId::new();
-    let amount = 115 * tickets.get() as i32;
+    let ticket_types = event::ticket_types(&mut *tx, event.id).await?;
+    let mut order_tickets = Vec::with_capacity(total);
 
-    let order = sqlx::query_as!(
-        Order,
-        "INSERT INTO orders (id, email, name, phone, amount) VALUES ($1, $2, $3, $4, $5) RETURNING *",
-        order_id.as_ref(),
-        email,
-        name,
-        phone,
-        amount,
-    )
-    .fetch_one(&mut *tx)
-    .await?;
+    for (ticket_type_id, count) in counts {
+        let ticket_type = ticket_types
+            .iter()
+            .find(|t| t.id == ticket_type_id && (t.public || is_admin))
+            .ok_or(EventError::TicketTypeNotFound)?;
 
-    insert_tickets(&mut *tx, order_id, tickets.get()).await?;
+        if count > remaining.of(ticket_type.id).try_into().unwrap() {
+            return Err(CreateOrderError::TooManyTickets.into());
+        }
+
+        order_tickets.extend(iter::repeat_n((ticket_type.id, ticket_type.price), count));
+    }
 
-    send_order_confirmation(&state.smtp, mbox, &order).await?;
+    let order = insert_order(
+        &mut tx,
+        &state,
+        &event,
+        Customer {
+            email: &email,
+            name: &name,
+            phone: &phone,
+            locale,
+        },
+        &order_tickets,
+        identity.as_ref(),
+    )
+    .await?;
 
     tx.commit().await?;
+    request_payment(&state, &order);
 
     Ok((StatusCode::CREATED, Json(order)))
 }
@@ -117,11 +319,17 @@ struct DetailedOrder {
     #[serde(flatten)]
     order: Order,
     tickets: Vec<Ticket>,
+    refunded: Decimal,
+    /// Paid for canceled tickets or orders and not yet refunded.
+    refund_due: Decimal,
+    refund_pending: bool,
 }
 
-async fn list_orders(state: AppState, _identity: Identity) -> Result<Json<Vec<DetailedOrder>>> {
+async fn list_orders(state: AppState, identity: Identity) -> Result<Json<Vec<DetailedOrder>>> {
+    identity.require(&[Role::Admin, Role::Viewer])?;
+
     let records = sqlx::query!(
-        "SELECT
+        r#"SELECT
     o.id as order_id,
     o.email as email,
     o.name as name,
@@ -129,15 +337,25 @@ async fn list_orders(state: AppState, _identity: Identity) -> Result<Json<Vec<De
     o.amount as amount,
     o.created_at as created_at,
     o.paid_at as paid_at,
-    o.completed_at as completed_at,
+    o.checked_out_at as checked_out_at,
     o.canceled_at as canceled_at,
     o.emailed_at as emailed_at,
+    o.event_id as event_id,
+    o.reminded_at as reminded_at,
+    o.locale as locale,
+    o.status as "status: OrderStatus",
+    (SELECT COALESCE(SUM(a.amount), 0) FROM payment_allocations a WHERE a.order_id = o.id AND a.removed_at IS NULL) as "allocated!",
+    (SELECT COALESCE(SUM(r.amount), 0) FROM refunds r WHERE r.order_id = o.id) as "refunded!",
     t.id as ticket_id,
-    t.scanned_at as scanned_at
+    t.scanned_at as scanned_at,
+    t.ticket_type_id as ticket_type_id,
+    t.price as price,
+    t.canceled_at as ticket_canceled_at,
+    t.canceled_by as canceled_by
   FROM
     orders o
     JOIN tickets t ON o.id = t.order_id
-    "
+    "#
     )
     .fetch_all(&state.pool)
     .await?;
@@ -148,28 +366,42 @@ async fn list_orders(state: AppState, _identity: Identity) -> Result<Json<Vec<De
         let order_id: OrderId = record.order_id.into();
         let ticket_id = record.ticket_id;
 
-        let order = orders
-            .entry(order_id.clone())
-            .or_insert_with(|| DetailedOrder {
-                order: Order {
-                    id: order_id.clone(),
-                    email: record.email,
-                    name: record.name,
-                    phone: record.phone,
-                    amount: record.amount,
-                    created_at: record.created_at,
-                    paid_at: record.paid_at,
-                    completed_at: record.completed_at,
-                    canceled_at: record.canceled_at,
-                    emailed_at: record.emailed_at,
-                },
+        let order = orders.entry(order_id.clone()).or_insert_with(|| {
+            let order = Order {
+                id: order_id.clone(),
+                email: record.email,
+                name: record.name,
+                phone: record.phone,
+                amount: record.amount,
+                created_at: record.created_at,
+                paid_at: record.paid_at,
+                checked_out_at: record.checked_out_at,
+                canceled_at: record.canceled_at,
+                emailed_at: record.emailed_at,
+                event_id: record.event_id,
+                reminded_at: record.reminded_at,
+                locale: record.locale.into(),
+                status: record.status,
+            };
+            let refund_due = refund::due(&order, record.allocated, record.refunded);
+
+            DetailedOrder {
+                order,
                 tickets: Vec::new(),
-            });
+                refunded: record.refunded,
+                refund_due,
+                refund_pending: refund_due > Decimal::ZERO,
+            }
+        });
 
         order.tickets.push(Ticket {
             id: ticket_id,
             order_id,
             scanned_at: record.scanned_at,
+            ticket_type_id: record.ticket_type_id,
+            price: record.price,
+            canceled_at: record.ticket_canceled_at,
+            canceled_by: record.canceled_by,
         });
     }
 
@@ -185,135 +417,344 @@ async fn get_order(order: Order) -> Result<impl IntoResponse> {
     Ok(Json(order))
 }
 
-async fn complete_order(order: Order, state: AppState) -> Result<impl IntoResponse> {
-    if order.completed_at.is_some() {
-        return Ok(Json(order));
-    }
+/// The customer says they have paid. Checking out again does nothing.
+async fn check_out_order(
+    order: Order,
+    state: AppState,
+    identity: Option<Identity>,
+) -> Result<impl IntoResponse> {
+    let mut tx = state.pool.begin().await?;
 
-    let order = sqlx::query_as!(
-        Order,
-        "UPDATE orders SET completed_at = NOW() WHERE id = $1 RETURNING *",
-        order.id.as_ref(),
+    let Some(order) = order::check_out(&mut *tx, &order).await? else {
+        return Ok(Json(order));
+    };
+    order::record(
+        &mut *tx,
+        &order,
+        Change::new(OrderEventKind::CheckedOut, identity.as_ref()),
     )
-    .fetch_one(&state.pool)
     .await?;
 
+    tx.commit().await?;
+
     Ok(Json(order))
 }
 
 #[derive(Debug, thiserror::Error)]
 enum CancelOrderError {
-    #[error("order completed")]
-    OrderCompleted,
+    #[error("order can only be canceled by an admin")]
+    NotCancelable,
 }
 
 impl From<CancelOrderError> for ResponseError {
     fn from(value: CancelOrderError) -> Self {
         match value {
-            CancelOrderError::OrderCompleted => Self::new(Code::OrderCompleted, "order completed"),
+            CancelOrderError::NotCancelable => {
+                Self::new(Code::OrderNotCancelable, value.to_string())
+            }
         }
     }
 }
 
-async fn cancel_order(order: Order, state: AppState) -> Result<impl IntoResponse> {
-    if order.canceled_at.is_some() {
-        return Ok(Json(order));
+/// Whether `order` may be canceled, in part or in full, by `identity`. Admins
+/// can cancel any order, see [`Order::customer_can_cancel`] for customers.
+pub(super) fn check_cancelable(order: &Order, identity: Option<&Identity>) -> Result<()> {
+    let is_admin = identity.is_some_and(|i| i.require(&[Role::Admin]).is_ok());
+
+    if !is_admin && !order.customer_can_cancel() {
+        return Err(CancelOrderError::NotCancelable.into());
     }
 
-    if order.completed_at.is_some() {
-        return Err(CancelOrderError::OrderCompleted.into());
+    Ok(())
+}
+
+async fn cancel_order(
+    order: Order,
+    state: AppState,
+    identity: Option<Identity>,
+) -> Result<impl IntoResponse> {
+    if order.status.is_canceled() {
+        return Ok(Json(order));
     }
 
-    let order = sqlx::query_as!(
-        Order,
-        "UPDATE orders SET canceled_at = NOW() WHERE id = $1 RETURNING *",
-        order.id.as_ref(),
+    check_cancelable(&order, identity.as_ref())?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let order = order::transition(
+        &mut tx,
+        &order,
+        order.status.on_cancel(),
+        Change::new(OrderEventKind::Canceled, identity.as_ref()),
     )
-    .fetch_one(&state.pool)
     .await?;
 
+    tx.commit().await?;
+    cancel_payment_requests(&state, &order);
+
     Ok(Json(order))
 }
 
+/// Every recorded change to `order`, oldest first.
+async fn get_history(
+    order: Order,
+    state: AppState,
+    identity: Identity,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin, Role::Viewer])?;
+
+    Ok(Json(history::for_order(&state.pool, &order.id).await?))
+}
+
+#[derive(Debug, Serialize)]
+struct SignedTicket {
+    #[serde(flatten)]
+    ticket: Ticket,
+    /// Signed code that can be verified offline.
+    code: String,
+}
+
 async fn get_tickets(order: Order, state: AppState) -> Result<impl IntoResponse> {
     let tickets = sqlx::query_as!(
         Ticket,
-        "SELECT * FROM tickets WHERE order_id = $1",
+        "SELECT * FROM tickets WHERE order_id = $1 AND canceled_at IS NULL",
         order.id.as_ref(),
     )
     .fetch_all(&state.pool)
-    .await?;
+    .await?
+    .into_iter()
+    .map(|ticket| {
+        let code = state.ticket_signer.sign(&TicketClaims {
+            ticket_id: ticket.id,
+            event_id: order.event_id,
+            order_id: order.id.clone(),
+        });
+
+        SignedTicket { ticket, code }
+    })
+    .collect::<Vec<_>>();
 
     Ok(Json(tickets))
 }
 
+/// Render the tickets of `order` as a printable PDF.
+async fn tickets_pdf(state: &AppState, event: &Event, order: &Order) -> Result<Vec<u8>> {
+    let pages = sqlx::query!(
+        "SELECT t.id, tt.name AS ticket_type
+    FROM tickets t
+    JOIN ticket_types tt ON tt.id = t.ticket_type_id
+    WHERE t.order_id = $1 AND t.canceled_at IS NULL
+    ORDER BY tt.name, t.id",
+        order.id.as_ref(),
+    )
+    .fetch_all(&state.pool)
+    .await?
+    .into_iter()
+    .map(|r| TicketPage {
+        ticket_id: r.id,
+        ticket_type: r.ticket_type,
+        code: state.ticket_signer.sign(&TicketClaims {
+            ticket_id: r.id,
+            event_id: order.event_id,
+            order_id: order.id.clone(),
+        }),
+    })
+    .collect::<Vec<_>>();
+
+    Ok(pdf::tickets(event, order, &pages)?)
+}
+
+async fn get_tickets_pdf(order: Order, state: AppState) -> Result<impl IntoResponse> {
+    let event = event::get(&state.pool, order.event_id).await?;
+    let pdf = tickets_pdf(&state, &event, &order).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_owned()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"biljetter-{}.pdf\"", order.id),
+            ),
+        ],
+        pdf,
+    ))
+}
+
+/// A prefilled Swish payment of `order`, which is only valid while the order
+/// is reserved.
+fn payment_request(state: &AppState, order: &Order) -> Result<PaymentRequest> {
+    if order.status != OrderStatus::Reserved {
+        return Err(ResponseError::new(
+            Code::OrderNotPayable,
+            format!("order is {}", order.status),
+        ));
+    }
+
+    Ok(PaymentRequest::for_order(&state.email.payee, order))
+}
+
+/// A prefilled Swish payment of the order, and a link to the request sent
+/// through the Swish API if there is one.
+async fn get_payment_request(order: Order, state: AppState) -> Result<impl IntoResponse> {
+    let request = payment_request(&state, &order)?;
+
+    Ok(Json(
+        match commerce::open_token(&state.pool, &order.id).await? {
+            Some(token) => request.with_token(&token),
+            None => request,
+        },
+    ))
+}
+
+/// The prefilled payment as an SVG QR code, to be scanned from the Swish app.
+/// Not cached, since it is only valid while the order is unpaid.
+async fn get_payment_qr_svg(order: Order, state: AppState) -> Result<impl IntoResponse> {
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "private, no-cache"),
+        ],
+        qr::svg(&payment_request(&state, &order)?.qr_data)?,
+    ))
+}
+
+/// The prefilled payment as a PNG QR code, to be scanned from the Swish app.
+async fn get_payment_qr_png(order: Order, state: AppState) -> Result<impl IntoResponse> {
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CACHE_CONTROL, "private, no-cache"),
+        ],
+        qr::png(&payment_request(&state, &order)?.qr_data)?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct SwishQuery {
+    /// Only report what would change.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct SwishImport {
+    #[serde(flatten)]
+    import: payment::Import,
+    report: reconcile::Report,
+    dry_run: bool,
+}
+
+/// Store the transactions of a Swish report, mark the orders they pay for as
+/// paid and return how each new transaction was matched.
+///
+/// With `?dry_run=true` the same report is returned but nothing is saved, so
+/// that it can be reviewed first.
 async fn swish(
     state: AppState,
+    identity: Identity,
+    Query(query): Query<SwishQuery>,
     mut multipart: axum::extract::Multipart,
 ) -> Result<impl IntoResponse> {
-    let field = multipart.next_field().await.unwrap().unwrap();
-    let data = field.bytes().await.unwrap();
-    let data = swish::parse_transactions(&data).unwrap();
+    identity.require(&[Role::Admin])?;
+
+    let field = multipart
+        .next_field()
+        .await
+        .map_err(|e| swish::invalid_upload(e.body_text()))?
+        .ok_or_else(|| swish::invalid_upload("no file uploaded"))?;
+    let filename = field.file_name().map(ToOwned::to_owned);
+    let data = field
+        .bytes()
+        .await
+        .map_err(|e| swish::invalid_upload(e.body_text()))?;
+
+    if data.is_empty() {
+        return Err(swish::invalid_upload("the uploaded file is empty"));
+    }
+
+    let data = swish::parse_transactions(&data)?;
 
     let mut tx = state.pool.begin().await?;
 
-    // build an sql query that sets the paid_at field to the time of the transaction
-    // for each transaction in the data
-    // let mut query_builder = sqlx::QueryBuilder::new("UPDATE orders SET paid_at = $1 WHERE id = $2");
-    // query_builder.push_values(
-    //     data.iter(),
-    //     |mut b, transaction| {
-    //         b.push_bind(transaction.time).push_bind(transaction.message);
-    //     },
-    // );
-
-    for transaction in data.iter() {
-        let Some(amount) = transaction.amount.to_i32() else {
-            continue;
-        };
-        sqlx::query!(
-            "UPDATE orders SET paid_at = $1 WHERE id = $2 AND amount = $3",
-            transaction.time,
-            transaction.message,
-            amount,
-        )
-        .execute(&mut *tx)
-        .await?;
-    }
+    let mut import = payment::import(&mut tx, filename.as_deref(), &identity.email, data).await?;
+    let report = reconcile::reconcile(&mut *tx, std::mem::take(&mut import.payments)).await?;
+    reconcile::apply(&mut tx, &report, Some(&identity)).await?;
 
-    tx.commit().await?;
+    // everything is done inside the transaction so that a dry run sees the
+    // same thing as a real import would
+    if query.dry_run {
+        tx.rollback().await?;
+    } else {
+        tx.commit().await?;
+    }
 
-    Ok(StatusCode::ACCEPTED)
+    Ok(Json(SwishImport {
+        import,
+        report,
+        dry_run: query.dry_run,
+    }))
 }
 
 async fn order_to_email(executor: impl PgExecutor<'_>) -> Result<Option<Order>> {
     Ok(sqlx::query_as!(
         Order,
-        "SELECT * FROM orders WHERE emailed_at IS NULL AND paid_at IS NOT NULL ORDER BY random() LIMIT 1"
+        r#"SELECT
+      id,
+      email,
+      name,
+      phone,
+      amount,
+      created_at,
+      checked_out_at,
+      paid_at,
+      canceled_at,
+      emailed_at,
+      event_id,
+      reminded_at,
+      status AS "status: OrderStatus",
+      locale
+    FROM orders
+    WHERE status = $1 ORDER BY random() LIMIT 1"#,
+        OrderStatus::Paid.as_str(),
     )
     .fetch_optional(executor)
     .await?)
 }
 
-async fn email_tickets(state: AppState, _identity: Identity) -> Result<impl IntoResponse> {
+async fn email_tickets(state: AppState, identity: Identity) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
     let mut tx = state.pool.begin().await?;
     let mut count = 0;
 
     while let Some(order) = order_to_email(&mut *tx).await? {
-        let mbox = Mailbox::new(Some(order.name.clone()), order.email.parse().unwrap());
-        if let Err(err) = send_tickets(&state.smtp, mbox, &order).await {
-            tracing::error!("failed to send tickets to {}: {}", order.email, err);
-            break;
-        }
-        sqlx::query!(
-            "UPDATE orders SET emailed_at = NOW() WHERE id = $1",
-            order.id.as_ref()
+        let event = event::get(&mut *tx, order.event_id).await?;
+        let pdf = tickets_pdf(&state, &event, &order).await?;
+        let message = email::compose(
+            &mut *tx,
+            &state.email,
+            EmailKind::Tickets,
+            &event,
+            &order,
+            state.payment_deadline,
+            Some(email::tickets_attachment(&order, pdf)),
+        )
+        .await?;
+        outbox::enqueue(
+            &mut *tx,
+            EmailKind::Tickets.as_str(),
+            Some(&order.id),
+            &message,
+        )
+        .await?;
+        order::transition(
+            &mut tx,
+            &order,
+            OrderStatus::Ticketed,
+            Change::new(OrderEventKind::Emailed, Some(&identity)),
         )
-        .execute(&mut *tx)
         .await?;
-        tx.commit().await?;
         count += 1;
-        tx = state.pool.begin().await?;
     }
 
     tx.commit().await?;
@@ -327,6 +768,20 @@ pub fn routes() -> Router<AppState> {
         .route("/swish", post(swish))
         .route("/email", post(email_tickets))
         .route("/:order_id", get(get_order).delete(cancel_order))
-        .route("/:order_id/complete", post(complete_order))
+        .route("/:order_id/checkout", post(check_out_order))
+        .route("/:order_id/history", get(get_history))
         .route("/:order_id/tickets", get(get_tickets))
+        .route("/:order_id/tickets.pdf", get(get_tickets_pdf))
+        .route("/:order_id/tickets/:id", delete(tickets::cancel_ticket))
+        .route("/:order_id/swish", get(get_payment_request))
+        .route("/:order_id/swish/qr.svg", get(get_payment_qr_svg))
+        .route("/:order_id/swish/qr.png", get(get_payment_qr_png))
+        .route(
+            "/:order_id/tickets/:id/qr.svg",
+            get(tickets::get_ticket_qr_svg),
+        )
+        .route(
+            "/:order_id/tickets/:id/qr.png",
+            get(tickets::get_ticket_qr_png),
+        )
 }
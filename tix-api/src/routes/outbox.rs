@@ -0,0 +1,94 @@
+use axum::{
+    extract::{Path, Query},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    error::{Code, ResponseError, Result},
+    order::OrderId,
+    outbox::{OutboxEntry, OutboxStatus},
+    staff::Role,
+};
+
+use super::{auth::Identity, AppState};
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    status: Option<OutboxStatus>,
+}
+
+/// Queued emails, by default only those that have not been sent.
+async fn list_outbox(
+    state: AppState,
+    identity: Identity,
+    Query(query): Query<ListQuery>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let entries = sqlx::query_as!(
+        OutboxEntry,
+        r#"SELECT
+    id,
+    kind,
+    order_id AS "order_id: OrderId",
+    envelope_to,
+    status AS "status: OutboxStatus",
+    attempts,
+    last_error,
+    next_attempt_at,
+    created_at,
+    sent_at
+  FROM outbox
+  WHERE ($1::outbox_status IS NULL AND status <> 'sent') OR status = $1
+  ORDER BY created_at DESC
+  LIMIT 500"#,
+        query.status as Option<OutboxStatus>,
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(entries))
+}
+
+/// Queue a dead (or pending) message for immediate delivery.
+async fn retry_outbox(
+    state: AppState,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let entry = sqlx::query_as!(
+        OutboxEntry,
+        r#"UPDATE outbox
+  SET status = 'pending', attempts = 0, next_attempt_at = NOW()
+  WHERE id = $1 AND status <> 'sent'
+  RETURNING
+    id,
+    kind,
+    order_id AS "order_id: OrderId",
+    envelope_to,
+    status AS "status: OutboxStatus",
+    attempts,
+    last_error,
+    next_attempt_at,
+    created_at,
+    sent_at"#,
+        id,
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(|| ResponseError::new(Code::OutboxEntryNotFound, "outbox entry not found"))?;
+
+    Ok(Json(entry))
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::<AppState>::new()
+        .route("/", get(list_outbox))
+        .route("/:id/retry", post(retry_outbox))
+}
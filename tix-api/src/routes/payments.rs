@@ -0,0 +1,455 @@
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    error::{Code, ResponseError, Result},
+    history::OrderEventKind,
+    order::{self, Change, Order, OrderId},
+    payment::{
+        self, Allocation, NewAllocation, Payment, PaymentError, PaymentImport, PaymentMethod,
+        PaymentStatus,
+    },
+    reconcile,
+    refund::{self, NewRefund, Refund},
+    staff::Role,
+    swish::commerce::{self, CommerceError},
+};
+
+use super::{auth::Identity, AppState};
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    status: Option<PaymentStatus>,
+    import_id: Option<Uuid>,
+    order_id: Option<OrderId>,
+}
+
+async fn list_payments(
+    state: AppState,
+    identity: Identity,
+    Query(query): Query<ListQuery>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin, Role::Viewer])?;
+
+    let payments = sqlx::query_as!(
+        Payment,
+        r#"SELECT
+    id,
+    import_id,
+    row,
+    paid_at,
+    amount,
+    message,
+    payor_phone,
+    payor_name,
+    payee_phone,
+    payee_name,
+    order_id AS "order_id: OrderId",
+    status AS "status: PaymentStatus",
+    created_at
+  FROM payments
+  WHERE ($1::payment_status IS NULL OR status = $1)
+    AND ($2::uuid IS NULL OR import_id = $2)
+    AND ($3::text IS NULL OR order_id = $3)
+  ORDER BY paid_at DESC, row DESC"#,
+        query.status as Option<PaymentStatus>,
+        query.import_id,
+        query.order_id.as_ref().map(AsRef::as_ref),
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(payments))
+}
+
+async fn list_imports(state: AppState, identity: Identity) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin, Role::Viewer])?;
+
+    let imports = sqlx::query_as!(
+        PaymentImport,
+        "SELECT * FROM payment_imports ORDER BY created_at DESC"
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(imports))
+}
+
+/// Try to match payments that were not matched when they were imported,
+/// e.g. after an order has been corrected.
+async fn rematch(state: AppState, identity: Identity) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let payments = payment::unmatched(&mut *tx).await?;
+    let report = reconcile::reconcile(&mut *tx, payments).await?;
+    reconcile::apply(&mut tx, &report, Some(&identity)).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(report))
+}
+
+#[derive(Debug, Deserialize)]
+struct AllocationsQuery {
+    payment_id: Option<Uuid>,
+    order_id: Option<OrderId>,
+}
+
+/// Allocations, including those that have been undone, newest first.
+async fn list_allocations(
+    state: AppState,
+    identity: Identity,
+    Query(query): Query<AllocationsQuery>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin, Role::Viewer])?;
+
+    let allocations = sqlx::query_as!(
+        Allocation,
+        r#"SELECT
+    id,
+    payment_id,
+    order_id,
+    amount,
+    method AS "method: PaymentMethod",
+    note,
+    created_by,
+    created_at,
+    removed_by,
+    removed_at
+  FROM payment_allocations
+  WHERE ($1::uuid IS NULL OR payment_id = $1)
+    AND ($2::text IS NULL OR order_id = $2)
+  ORDER BY created_at DESC"#,
+        query.payment_id,
+        query.order_id.as_ref().map(AsRef::as_ref),
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(allocations))
+}
+
+fn order_not_found() -> ResponseError {
+    ResponseError::new(Code::OrderNotFound, "order not found")
+}
+
+/// The order `order_id`, locked until the end of the transaction.
+async fn lock_order(executor: impl sqlx::PgExecutor<'_>, order_id: &OrderId) -> Result<Order> {
+    order::lock(executor, order_id)
+        .await?
+        .ok_or_else(order_not_found)
+}
+
+#[derive(Debug, Deserialize)]
+struct AllocationRequest {
+    order_id: OrderId,
+    /// Defaults to what is left of the payment.
+    amount: Option<Decimal>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AllocateRequest {
+    allocations: Vec<AllocationRequest>,
+    note: Option<String>,
+}
+
+/// Attach a payment to one or more orders, splitting it between them.
+async fn allocate_payment(
+    state: AppState,
+    identity: Identity,
+    Path(payment_id): Path<Uuid>,
+    Json(req): Json<AllocateRequest>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    if req.allocations.is_empty() {
+        return Err(PaymentError::InvalidAllocation("no orders given").into());
+    }
+
+    let mut tx = state.pool.begin().await?;
+
+    let payment = sqlx::query!(
+        "SELECT amount, paid_at FROM payments WHERE id = $1 FOR UPDATE",
+        payment_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(PaymentError::PaymentNotFound)?;
+
+    let mut remaining =
+        payment.amount - payment::allocated_from_payment(&mut *tx, payment_id).await?;
+    let mut allocations = Vec::with_capacity(req.allocations.len());
+
+    for allocation in req.allocations {
+        let order = lock_order(&mut *tx, &allocation.order_id).await?;
+
+        let amount = allocation.amount.unwrap_or(remaining);
+        if amount <= Decimal::ZERO {
+            return Err(PaymentError::InvalidAllocation("nothing left of the payment").into());
+        }
+        if amount > remaining {
+            return Err(PaymentError::InvalidAllocation("allocations exceed the payment").into());
+        }
+        remaining -= amount;
+
+        allocations.push(
+            payment::allocate(
+                &mut *tx,
+                NewAllocation {
+                    payment_id: Some(payment_id),
+                    order_id: &allocation.order_id,
+                    amount,
+                    method: PaymentMethod::Swish,
+                    note: req.note.as_deref(),
+                    created_by: Some(&identity.email),
+                },
+            )
+            .await?,
+        );
+        order::refresh_paid(
+            &mut tx,
+            &order,
+            payment.paid_at,
+            Change::new(OrderEventKind::PaymentAdded, Some(&identity)),
+        )
+        .await?;
+    }
+
+    sqlx::query!(
+        "UPDATE payments SET status = 'manual' WHERE id = $1",
+        payment_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(allocations)))
+}
+
+#[derive(Debug, Deserialize)]
+struct ManualPaymentRequest {
+    order_id: OrderId,
+    method: PaymentMethod,
+    /// Defaults to what is left to pay.
+    amount: Option<Decimal>,
+    /// Where the money came from, e.g. who received the cash.
+    note: String,
+}
+
+/// Record a payment made outside of Swish, e.g. in cash.
+async fn manual_payment(
+    state: AppState,
+    identity: Identity,
+    Json(req): Json<ManualPaymentRequest>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let order = lock_order(&mut *tx, &req.order_id).await?;
+    let amount = match req.amount {
+        Some(amount) => amount,
+        None => {
+            Decimal::from(order.amount) - payment::allocated_to_order(&mut *tx, &order.id).await?
+        }
+    };
+
+    if amount <= Decimal::ZERO {
+        return Err(PaymentError::InvalidAllocation("nothing left to pay").into());
+    }
+
+    let allocation = payment::allocate(
+        &mut *tx,
+        NewAllocation {
+            payment_id: None,
+            order_id: &req.order_id,
+            amount,
+            method: req.method,
+            note: Some(&req.note),
+            created_by: Some(&identity.email),
+        },
+    )
+    .await?;
+    order::refresh_paid(
+        &mut tx,
+        &order,
+        OffsetDateTime::now_utc(),
+        Change::new(OrderEventKind::PaymentAdded, Some(&identity)),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(allocation)))
+}
+
+/// Undo an allocation, which makes the order unpaid again unless it is
+/// covered by other allocations.
+async fn remove_allocation(
+    state: AppState,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let allocation = payment::remove_allocation(&mut *tx, id, &identity.email)
+        .await?
+        .ok_or(PaymentError::AllocationNotFound)?;
+
+    let order = lock_order(&mut *tx, &allocation.order_id).await?;
+    order::refresh_paid(
+        &mut tx,
+        &order,
+        OffsetDateTime::now_utc(),
+        Change::new(OrderEventKind::PaymentRemoved, Some(&identity)),
+    )
+    .await?;
+
+    if let Some(payment_id) = allocation.payment_id {
+        // keep automatic matching from putting it back
+        sqlx::query!(
+            "UPDATE payments SET status = 'manual' WHERE id = $1",
+            payment_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(allocation))
+}
+
+#[derive(Debug, Deserialize)]
+struct RefundsQuery {
+    order_id: Option<OrderId>,
+}
+
+/// Refunds, newest first.
+async fn list_refunds(
+    state: AppState,
+    identity: Identity,
+    Query(query): Query<RefundsQuery>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin, Role::Viewer])?;
+
+    let refunds = sqlx::query_as!(
+        Refund,
+        r#"SELECT
+    id,
+    order_id,
+    amount,
+    method AS "method: PaymentMethod",
+    reference,
+    note,
+    approved_by,
+    created_at
+  FROM refunds
+  WHERE ($1::text IS NULL OR order_id = $1)
+  ORDER BY created_at DESC"#,
+        query.order_id.as_ref().map(AsRef::as_ref),
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(refunds))
+}
+
+#[derive(Debug, Deserialize)]
+struct RefundRequest {
+    order_id: OrderId,
+    method: PaymentMethod,
+    /// Defaults to what is left to refund.
+    amount: Option<Decimal>,
+    reference: Option<String>,
+    note: Option<String>,
+}
+
+/// Record money paid back to the customer, by the admin making the request.
+async fn create_refund(
+    state: AppState,
+    identity: Identity,
+    Json(req): Json<RefundRequest>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let order = lock_order(&mut *tx, &req.order_id).await?;
+    let due = refund::due_for_order(&mut tx, &order).await?;
+    let amount = req.amount.unwrap_or(due);
+
+    if amount <= Decimal::ZERO {
+        return Err(PaymentError::InvalidRefund("nothing to refund").into());
+    }
+    if amount > due {
+        return Err(PaymentError::InvalidRefund("refund exceeds what is due").into());
+    }
+
+    let refund = refund::record(
+        &mut *tx,
+        NewRefund {
+            order_id: &order.id,
+            amount,
+            method: req.method,
+            reference: req.reference.as_deref(),
+            note: req.note.as_deref(),
+            approved_by: &identity.email,
+        },
+    )
+    .await?;
+    order::record(
+        &mut *tx,
+        &order,
+        Change::new(OrderEventKind::Refunded, Some(&identity)),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(refund)))
+}
+
+#[derive(Debug, Deserialize)]
+struct SwishCallback {
+    id: String,
+}
+
+/// Called by Swish when a payment request changes status. Anyone can call
+/// this, so the status is fetched from Swish rather than taken from the body.
+async fn swish_callback(
+    state: AppState,
+    Json(callback): Json<SwishCallback>,
+) -> Result<impl IntoResponse> {
+    let client = state.swish.as_ref().ok_or(CommerceError::NotFound)?;
+    commerce::sync(&state.pool, client, &callback.id).await?;
+
+    Ok(StatusCode::OK)
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::<AppState>::new()
+        .route("/", get(list_payments))
+        .route("/imports", get(list_imports))
+        .route("/rematch", post(rematch))
+        .route("/manual", post(manual_payment))
+        .route("/allocations", get(list_allocations))
+        .route("/allocations/:id", delete(remove_allocation))
+        .route("/refunds", get(list_refunds).post(create_refund))
+        .route("/:payment_id/allocations", post(allocate_payment))
+        .route("/swish/callback", post(swish_callback))
+}
@@ -0,0 +1,70 @@
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, put},
+    Json, Router,
+};
+use serde::Deserialize;
+
+use crate::{
+    error::{Code, ResponseError, Result},
+    staff::{self, Role},
+};
+
+use super::{auth::Identity, AppState};
+
+async fn list_staff(state: AppState, identity: Identity) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    Ok(Json(staff::list(&state.pool).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct InviteStaff {
+    role: Role,
+}
+
+async fn invite_staff(
+    state: AppState,
+    identity: Identity,
+    Path(email): Path<String>,
+    Json(req): Json<InviteStaff>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    let staff = staff::upsert(&state.pool, &email, req.role, Some(&identity.email)).await?;
+
+    Ok(Json(staff))
+}
+
+async fn revoke_staff(
+    state: AppState,
+    identity: Identity,
+    Path(email): Path<String>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin])?;
+
+    if email.eq_ignore_ascii_case(&identity.email) {
+        return Err(ResponseError::new(
+            Code::Forbidden,
+            "cannot revoke your own access",
+        ));
+    }
+
+    let res = sqlx::query!("DELETE FROM staff WHERE email = $1", email.to_lowercase())
+        .execute(&state.pool)
+        .await?;
+
+    if res.rows_affected() == 0 {
+        return Err(ResponseError::new(Code::StaffNotFound, "staff not found"));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::<AppState>::new()
+        .route("/", get(list_staff))
+        .route("/:email", put(invite_staff).delete(revoke_staff))
+}
@@ -1,34 +1,160 @@
+use std::collections::HashMap;
+
 use axum::{
     extract::Path,
+    http::header,
     response::IntoResponse,
     routing::{get, post},
     Json, Router,
 };
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 use crate::{
     error::{Code, ResponseError, Result},
-    order::Order,
-    routes::orders::Ticket,
+    event::Event,
+    history::OrderEventKind,
+    order::{self, Change, Order, OrderStatus},
+    qr,
+    routes::orders::{cancel_payment_requests, check_cancelable, Ticket},
+    staff::Role,
+    ticket_code::{self, TicketClaims},
 };
 
 use super::{auth::Identity, AppState};
 
-const TICKETS_FOR_SALE: u32 = 266;
+#[derive(Debug, Serialize)]
+pub struct TicketsRemaining {
+    /// Tickets left for the event as a whole.
+    pub total: u32,
+    /// Tickets left per ticket type. Never more than `total`.
+    pub ticket_types: HashMap<Uuid, u32>,
+}
 
-pub async fn tickets_remaining(executor: impl sqlx::PgExecutor<'_>) -> sqlx::Result<u32> {
-    // count the number of tickets with a NULL order.canceled_at
-    let count = sqlx::query!("SELECT COUNT(*) FROM tickets LEFT JOIN orders ON tickets.order_id = orders.id WHERE orders.canceled_at IS NULL")
-        .fetch_one(executor)
-        .await?
-        .count
-        .and_then(|c| c.try_into().ok())
-        .unwrap_or(0);
+impl TicketsRemaining {
+    pub fn of(&self, ticket_type_id: Uuid) -> u32 {
+        self.ticket_types.get(&ticket_type_id).copied().unwrap_or(0)
+    }
+
+    /// Take `count` tickets of `ticket_type_id`, if there are that many left.
+    /// Returns whether they were taken.
+    pub fn take(&mut self, ticket_type_id: Uuid, count: u32) -> bool {
+        if count > self.of(ticket_type_id) {
+            return false;
+        }
+
+        self.total -= count;
+        if let Some(left) = self.ticket_types.get_mut(&ticket_type_id) {
+            *left -= count;
+        }
+        for left in self.ticket_types.values_mut() {
+            *left = (*left).min(self.total);
+        }
+
+        true
+    }
+}
+
+/// Tickets left for `event` that are neither sold nor offered to the
+/// [waitlist](crate::waitlist), and how many tickets of which type each
+/// person still waiting wants, in the order they signed up.
+async fn count_remaining(
+    executor: impl sqlx::PgExecutor<'_>,
+    event: &Event,
+) -> sqlx::Result<(TicketsRemaining, Vec<(Uuid, u32)>)> {
+    // count the tickets of each type that are not canceled, on their own or
+    // with their order, and those held for offers that have not lapsed
+    let rows = sqlx::query!(
+        r#"SELECT
+      tt.id,
+      tt.quota,
+      COUNT(t.id) FILTER (WHERE o.canceled_at IS NULL AND t.canceled_at IS NULL)
+        + (SELECT COALESCE(SUM(w.count), 0) FROM waitlist w WHERE w.ticket_type_id = tt.id AND w.status = 'offered' AND w.offer_expires_at > NOW()) AS "sold!",
+      ARRAY(SELECT w.id FROM waitlist w WHERE w.ticket_type_id = tt.id AND w.status = 'waiting' ORDER BY w.created_at, w.id) AS "waiting_ids!",
+      ARRAY(SELECT w.count FROM waitlist w WHERE w.ticket_type_id = tt.id AND w.status = 'waiting' ORDER BY w.created_at, w.id) AS "waiting_counts!",
+      ARRAY(SELECT w.created_at FROM waitlist w WHERE w.ticket_type_id = tt.id AND w.status = 'waiting' ORDER BY w.created_at, w.id) AS "waiting_since!"
+    FROM ticket_types tt
+    LEFT JOIN tickets t ON t.ticket_type_id = tt.id
+    LEFT JOIN orders o ON o.id = t.order_id
+    WHERE tt.event_id = $1
+    GROUP BY tt.id"#,
+        event.id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    let sold: u32 = rows
+        .iter()
+        .map(|r| u32::try_from(r.sold).unwrap_or(0))
+        .sum();
+    let capacity: u32 = event.capacity.try_into().unwrap_or(0);
+    let total = capacity.saturating_sub(sold);
+
+    let mut waiting = Vec::new();
+    let mut ticket_types = HashMap::with_capacity(rows.len());
 
-    Ok(TICKETS_FOR_SALE.saturating_sub(count))
+    for r in rows {
+        let quota: u32 = r.quota.try_into().unwrap_or(0);
+        let sold = u32::try_from(r.sold).unwrap_or(0);
+        ticket_types.insert(r.id, quota.saturating_sub(sold).min(total));
+
+        let entries = r
+            .waiting_since
+            .into_iter()
+            .zip(r.waiting_ids)
+            .zip(r.waiting_counts);
+        for ((signed_up, id), count) in entries {
+            let count = u32::try_from(count).unwrap_or(u32::MAX);
+            waiting.push(((signed_up, id), r.id, count));
+        }
+    }
+
+    waiting.sort_unstable_by_key(|(key, ..)| *key);
+
+    let remaining = TicketsRemaining {
+        total,
+        ticket_types,
+    };
+    let waiting = waiting
+        .into_iter()
+        .map(|(_, ticket_type_id, count)| (ticket_type_id, count))
+        .collect();
+
+    Ok((remaining, waiting))
 }
 
-async fn list_tickets(state: AppState) -> Result<impl IntoResponse> {
+/// Tickets that can still be ordered for `event`. Tickets offered to the
+/// [waitlist](crate::waitlist) are held, and count as sold until the offer is
+/// claimed or lapses. So are the tickets that the people waiting are about to
+/// be offered, so that tickets freed by canceled or expired orders go to the
+/// waitlist before anyone else.
+pub async fn tickets_remaining(
+    executor: impl sqlx::PgExecutor<'_>,
+    event: &Event,
+) -> sqlx::Result<TicketsRemaining> {
+    let (mut remaining, waiting) = count_remaining(executor, event).await?;
+
+    // the same first fit as when offering, see crate::waitlist
+    for (ticket_type_id, count) in waiting {
+        remaining.take(ticket_type_id, count);
+    }
+
+    Ok(remaining)
+}
+
+/// Tickets of `event` that can be offered to the waitlist.
+pub(crate) async fn tickets_unoffered(
+    executor: impl sqlx::PgExecutor<'_>,
+    event: &Event,
+) -> sqlx::Result<TicketsRemaining> {
+    Ok(count_remaining(executor, event).await?.0)
+}
+
+async fn list_tickets(state: AppState, identity: Identity) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin, Role::Scanner])?;
+
     let tickets = sqlx::query_as!(Ticket, "SELECT * FROM tickets ORDER BY id ASC")
         .fetch_all(&state.pool)
         .await?;
@@ -36,7 +162,7 @@ async fn list_tickets(state: AppState) -> Result<impl IntoResponse> {
     Ok(Json(tickets))
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Serialize)]
 pub struct Scan {
     pub ticket: Ticket,
     pub order: Order,
@@ -44,26 +170,48 @@ pub struct Scan {
     pub remaining_unscanned: usize,
 }
 
-async fn scan_ticket(
-    state: AppState,
-    Path(id): Path<Uuid>,
-    _ident: Identity,
-) -> Result<Json<Scan>> {
+async fn scan(state: &AppState, id: Uuid, identity: &Identity) -> Result<Scan> {
     let mut tx = state.pool.begin().await?;
 
+    // locked so that scanning the last two tickets at once completes the order
+    let order = sqlx::query_as!(
+        Order,
+        r#"SELECT
+      id,
+      email,
+      name,
+      phone,
+      amount,
+      created_at,
+      checked_out_at,
+      paid_at,
+      canceled_at,
+      emailed_at,
+      event_id,
+      reminded_at,
+      status AS "status: OrderStatus",
+      locale
+    FROM orders
+    WHERE id = (SELECT order_id FROM tickets WHERE id = $1) FOR UPDATE"#,
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| ResponseError::new(Code::TicketNotFound, "ticket not found"))?;
+
     let tickets = sqlx::query_as!(
         Ticket,
         "SELECT *
     FROM tickets
-    WHERE order_id = (SELECT order_id FROM tickets WHERE id = $1)",
-        id
+    WHERE order_id = $1",
+        order.id.as_ref()
     )
     .fetch_all(&mut *tx)
     .await?;
 
     let remaining_unscanned = tickets
         .iter()
-        .filter(|t| t.scanned_at.is_none() && t.id != id)
+        .filter(|t| t.scanned_at.is_none() && t.canceled_at.is_none() && t.id != id)
         .count();
     let mut ticket = tickets
         .into_iter()
@@ -71,15 +219,11 @@ async fn scan_ticket(
         .ok_or_else(|| ResponseError::new(Code::TicketNotFound, "ticket not found"))?;
     let already_scanned = ticket.scanned_at.is_some();
 
-    let order = sqlx::query_as!(
-        Order,
-        "SELECT * FROM orders WHERE id = $1",
-        ticket.order_id.as_ref()
-    )
-    .fetch_one(&mut *tx)
-    .await?;
+    if order.status.is_canceled() || ticket.canceled_at.is_some() {
+        return Err(ResponseError::new(Code::TicketNotFound, "ticket canceled"));
+    }
 
-    if order.paid_at.is_none() {
+    if !order.status.is_paid() {
         return Err(ResponseError::new(Code::TicketNotFound, "order not paid"));
     }
 
@@ -93,42 +237,287 @@ async fn scan_ticket(
         .await?;
     }
 
+    let change = Change {
+        kind: OrderEventKind::TicketScanned,
+        ticket_id: Some(id),
+        actor: Some(identity),
+    };
+    let order = if already_scanned {
+        order
+    } else if remaining_unscanned == 0 {
+        order::transition(&mut tx, &order, OrderStatus::Completed, change).await?
+    } else {
+        order::record(&mut *tx, &order, change).await?;
+        order
+    };
+
     tx.commit().await?;
 
-    Ok(Json(Scan {
+    Ok(Scan {
         ticket,
         order,
         already_scanned,
         remaining_unscanned,
-    }))
+    })
+}
+
+async fn scan_ticket(
+    state: AppState,
+    Path(id): Path<Uuid>,
+    identity: Identity,
+) -> Result<Json<Scan>> {
+    identity.require(&[Role::Admin, Role::Scanner])?;
+
+    Ok(Json(scan(&state, id, &identity).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct ScanCode {
+    code: String,
+}
+
+/// Scan a signed ticket code rather than a bare ticket id.
+async fn scan_code(
+    state: AppState,
+    identity: Identity,
+    Json(req): Json<ScanCode>,
+) -> Result<Json<Scan>> {
+    identity.require(&[Role::Admin, Role::Scanner])?;
+
+    let claims = ticket_code::verify(&state.ticket_signer.verifying_key(), &req.code)?;
+
+    Ok(Json(scan(&state, claims.ticket_id, &identity).await?))
+}
+
+#[derive(Debug, Serialize)]
+struct VerificationKey {
+    algorithm: &'static str,
+    /// Base64url-encoded public key, without padding.
+    public_key: String,
 }
 
-async fn get_tickets_remaining(state: AppState) -> Result<impl IntoResponse> {
-    let remaining = tickets_remaining(&state.pool).await?;
+async fn get_verification_key(state: AppState) -> impl IntoResponse {
+    Json(VerificationKey {
+        algorithm: "ed25519",
+        public_key: URL_SAFE_NO_PAD.encode(state.ticket_signer.verifying_key().as_bytes()),
+    })
+}
 
-    Ok(Json(remaining))
+#[derive(Debug, Serialize)]
+struct TicketTypeStats {
+    ticket_type_id: Uuid,
+    event_id: Uuid,
+    name: String,
+    /// Tickets that have not been canceled, on their own or with their
+    /// order.
+    reserved: u32,
+    paid: u32,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Serialize)]
 struct TicketStats {
     paid: u32,
+    ticket_types: Vec<TicketTypeStats>,
+}
+
+async fn get_ticket_stats(state: AppState, identity: Identity) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin, Role::Viewer, Role::Scanner])?;
+
+    let ticket_types = sqlx::query!(
+        r#"SELECT
+    tt.id,
+    tt.event_id,
+    tt.name,
+    COUNT(t.id) FILTER (WHERE o.canceled_at IS NULL AND t.canceled_at IS NULL) AS "reserved!",
+    COUNT(t.id) FILTER (WHERE o.paid_at IS NOT NULL AND o.canceled_at IS NULL AND t.canceled_at IS NULL) AS "paid!"
+  FROM
+    ticket_types tt
+    LEFT JOIN tickets t ON t.ticket_type_id = tt.id
+    LEFT JOIN orders o ON o.id = t.order_id
+  GROUP BY tt.id
+  ORDER BY tt.event_id, tt.name"#
+    )
+    .fetch_all(&state.pool)
+    .await?
+    .into_iter()
+    .map(|r| TicketTypeStats {
+        ticket_type_id: r.id,
+        event_id: r.event_id,
+        name: r.name,
+        reserved: r.reserved.try_into().unwrap_or(0),
+        paid: r.paid.try_into().unwrap_or(0),
+    })
+    .collect::<Vec<_>>();
+
+    let paid = ticket_types.iter().map(|t| t.paid).sum();
+
+    Ok(Json(TicketStats { paid, ticket_types }))
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct TicketPath {
+    id: Uuid,
 }
 
-async fn get_ticket_stats(state: AppState, _ident: Identity) -> Result<impl IntoResponse> {
-    let paid: u32 = sqlx::query!("SELECT COUNT(*) FROM tickets LEFT JOIN orders ON tickets.order_id = orders.id WHERE orders.paid_at IS NOT NULL")
-        .fetch_one(&state.pool)
+/// Signed code for ticket `id`, which must belong to `order`.
+async fn signed_code(state: &AppState, order: &Order, id: Uuid) -> Result<String> {
+    let ticket = sqlx::query_as!(
+        Ticket,
+        "SELECT * FROM tickets WHERE id = $1 AND order_id = $2 AND canceled_at IS NULL",
+        id,
+        order.id.as_ref(),
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(|| ResponseError::new(Code::TicketNotFound, "ticket not found"))?;
+
+    Ok(state.ticket_signer.sign(&TicketClaims {
+        ticket_id: ticket.id,
+        event_id: order.event_id,
+        order_id: order.id.clone(),
+    }))
+}
+
+/// The signed ticket code as an SVG QR code.
+pub(super) async fn get_ticket_qr_svg(
+    order: Order,
+    state: AppState,
+    Path(TicketPath { id }): Path<TicketPath>,
+) -> Result<impl IntoResponse> {
+    let code = signed_code(&state, &order, id).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "private, max-age=86400"),
+        ],
+        qr::svg(&code)?,
+    ))
+}
+
+/// The signed ticket code as a PNG QR code.
+pub(super) async fn get_ticket_qr_png(
+    order: Order,
+    state: AppState,
+    Path(TicketPath { id }): Path<TicketPath>,
+) -> Result<impl IntoResponse> {
+    let code = signed_code(&state, &order, id).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CACHE_CONTROL, "private, max-age=86400"),
+        ],
+        qr::png(&code)?,
+    ))
+}
+
+/// Cancel a single ticket, which frees it and takes its price off the order.
+/// Canceling the last ticket cancels the order. Anything already paid for
+/// the ticket is due to be [refunded](crate::refund).
+pub(super) async fn cancel_ticket(
+    order: Order,
+    state: AppState,
+    identity: Option<Identity>,
+    Path(TicketPath { id }): Path<TicketPath>,
+) -> Result<impl IntoResponse> {
+    check_cancelable(&order, identity.as_ref())?;
+
+    let mut tx = state.pool.begin().await?;
+
+    // keep concurrent cancellations from both taking the last ticket
+    let order = order::lock(&mut *tx, &order.id)
         .await?
-        .count
-        .and_then(|c| c.try_into().ok())
-        .unwrap_or(0);
+        .ok_or_else(|| ResponseError::new(Code::OrderNotFound, "order not found"))?;
+
+    if order.status.is_canceled() {
+        return Err(ResponseError::new(Code::TicketNotFound, "ticket canceled"));
+    }
+
+    let ticket = sqlx::query_as!(
+        Ticket,
+        "SELECT * FROM tickets WHERE id = $1 AND order_id = $2 AND canceled_at IS NULL",
+        id,
+        order.id.as_ref(),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| ResponseError::new(Code::TicketNotFound, "ticket not found"))?;
+
+    // a used ticket is not refunded. Scanning locks the order too, so this
+    // cannot change before the ticket is canceled
+    if ticket.scanned_at.is_some() {
+        return Err(ResponseError::new(
+            Code::TicketScanned,
+            "ticket has been scanned",
+        ));
+    }
+
+    let ticket = sqlx::query_as!(
+        Ticket,
+        "UPDATE tickets SET canceled_at = NOW(), canceled_by = $2 WHERE id = $1 RETURNING *",
+        ticket.id,
+        identity.as_ref().map(|i| i.email.as_str()),
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let order = order::reduce_amount(&mut *tx, &order, ticket.price).await?;
+
+    let remaining = sqlx::query!(
+        r#"SELECT
+      COUNT(*) AS "live!",
+      COUNT(*) FILTER (WHERE scanned_at IS NULL) AS "unscanned!"
+    FROM tickets
+    WHERE order_id = $1 AND canceled_at IS NULL"#,
+        order.id.as_ref()
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let change = Change {
+        kind: OrderEventKind::TicketCanceled,
+        ticket_id: Some(ticket.id),
+        actor: identity.as_ref(),
+    };
+    if remaining.live == 0 {
+        order::record(&mut *tx, &order, change).await?;
+        order::transition(
+            &mut tx,
+            &order,
+            order.status.on_cancel(),
+            Change::new(OrderEventKind::Canceled, identity.as_ref()),
+        )
+        .await?;
+    } else {
+        // the remaining tickets may now be covered by what has been paid
+        let order = order::refresh_paid(&mut tx, &order, OffsetDateTime::now_utc(), change).await?;
+
+        // the rest of the tickets have been scanned, as when scanning the last
+        if remaining.unscanned == 0 && order.status.can_become(OrderStatus::Completed) {
+            order::transition(
+                &mut tx,
+                &order,
+                OrderStatus::Completed,
+                Change::new(OrderEventKind::Completed, identity.as_ref()),
+            )
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+    if remaining.live == 0 {
+        cancel_payment_requests(&state, &order);
+    }
 
-    Ok(Json(TicketStats { paid }))
+    Ok(Json(ticket))
 }
 
 pub fn routes() -> Router<AppState> {
     Router::<AppState>::new()
         .route("/", get(list_tickets))
-        .route("/remaining", get(get_tickets_remaining))
         .route("/stats", get(get_ticket_stats))
+        .route("/key", get(get_verification_key))
+        .route("/scan", post(scan_code))
         .route("/:id/scan", post(scan_ticket))
 }
@@ -0,0 +1,218 @@
+use std::{iter, num::NonZeroUsize};
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    error::Result,
+    event::{self, EventError},
+    locale::Locale,
+    order::OrderId,
+    staff::Role,
+    waitlist::{self, NewEntry, WaitlistEntry, WaitlistError, WaitlistStatus},
+};
+
+use super::{
+    auth::Identity,
+    orders::{insert_order, request_payment, CreateOrderError, Customer},
+    tickets::tickets_remaining,
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+struct JoinRequest {
+    email: String,
+    name: String,
+    phone: String,
+    event_id: Uuid,
+    ticket_type_id: Uuid,
+    count: NonZeroUsize,
+    #[serde(default)]
+    locale: Locale,
+}
+
+/// Sign up for tickets of one type if they become available. Signing up
+/// again changes the number of tickets wanted.
+async fn join_waitlist(state: AppState, Json(req): Json<JoinRequest>) -> Result<impl IntoResponse> {
+    let mut tx = state.pool.begin().await?;
+
+    // held while checking what is left, like when ordering
+    let event = event::lock(&mut *tx, req.event_id).await?;
+    if !event.is_on_sale(OffsetDateTime::now_utc()) {
+        return Err(EventError::SalesClosed.into());
+    }
+
+    event::ticket_types(&mut *tx, event.id)
+        .await?
+        .into_iter()
+        .find(|t| t.id == req.ticket_type_id && t.public)
+        .ok_or(EventError::TicketTypeNotFound)?;
+
+    if req.count > state.max_tickets_per_order {
+        return Err(CreateOrderError::TooManyTickets.into());
+    }
+
+    // the tickets would be held for the waitlist instead of being sold
+    let remaining = tickets_remaining(&mut *tx, &event).await?;
+    if usize::try_from(remaining.of(req.ticket_type_id)).unwrap() >= req.count.get() {
+        return Err(WaitlistError::TicketsAvailable.into());
+    }
+
+    let (entry, created) = waitlist::join(
+        &mut tx,
+        NewEntry {
+            event_id: event.id,
+            ticket_type_id: req.ticket_type_id,
+            count: req.count.get().try_into().unwrap(),
+            email: &req.email,
+            name: &req.name,
+            phone: &req.phone,
+            locale: req.locale,
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+
+    Ok((status, Json(entry)))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    event_id: Option<Uuid>,
+    status: Option<WaitlistStatus>,
+}
+
+/// Everyone on the waitlist, first signed up first.
+async fn list_waitlist(
+    state: AppState,
+    identity: Identity,
+    Query(query): Query<ListQuery>,
+) -> Result<impl IntoResponse> {
+    identity.require(&[Role::Admin, Role::Viewer])?;
+
+    let entries = sqlx::query_as!(
+        WaitlistEntry,
+        r#"SELECT
+    id,
+    event_id,
+    ticket_type_id,
+    count,
+    email,
+    name,
+    phone,
+    locale,
+    status AS "status: WaitlistStatus",
+    token,
+    offered_at,
+    offer_expires_at,
+    order_id AS "order_id: OrderId",
+    created_at
+  FROM waitlist
+  WHERE ($1::uuid IS NULL OR event_id = $1)
+    AND ($2::waitlist_status IS NULL OR status = $2)
+  ORDER BY created_at"#,
+        query.event_id,
+        query.status as Option<WaitlistStatus>,
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(entries))
+}
+
+#[derive(Debug, Deserialize)]
+struct Token {
+    token: String,
+}
+
+/// An entry and its offer, for the customer following the claim link.
+async fn get_entry(
+    state: AppState,
+    Path(id): Path<Uuid>,
+    Query(Token { token }): Query<Token>,
+) -> Result<impl IntoResponse> {
+    let entry = waitlist::get(&state.pool, id)
+        .await?
+        .filter(|entry| entry.token.as_deref() == Some(token.as_str()))
+        .ok_or(WaitlistError::NotFound)?;
+
+    Ok(Json(entry))
+}
+
+/// Order the tickets offered to an entry. They are held for it, so they are
+/// not checked against what is remaining.
+async fn claim_offer(
+    state: AppState,
+    Path(id): Path<Uuid>,
+    Json(Token { token }): Json<Token>,
+) -> Result<impl IntoResponse> {
+    let mut tx = state.pool.begin().await?;
+
+    let event_id = waitlist::get(&mut *tx, id)
+        .await?
+        .ok_or(WaitlistError::NotFound)?
+        .event_id;
+    // the event is locked before the entry, like when offering
+    let event = event::lock(&mut *tx, event_id).await?;
+    let entry = waitlist::get_with_token(&mut tx, id, &token).await?;
+    let now = OffsetDateTime::now_utc();
+    waitlist::check_claimable(&entry, now)?;
+
+    if !event.is_on_sale(now) {
+        return Err(EventError::SalesClosed.into());
+    }
+
+    let ticket_type = event::ticket_types(&mut *tx, event.id)
+        .await?
+        .into_iter()
+        .find(|t| t.id == entry.ticket_type_id)
+        .ok_or(EventError::TicketTypeNotFound)?;
+    let tickets = iter::repeat_n(
+        (ticket_type.id, ticket_type.price),
+        entry.count.try_into().unwrap(),
+    )
+    .collect::<Vec<_>>();
+
+    let order = insert_order(
+        &mut tx,
+        &state,
+        &event,
+        Customer {
+            email: &entry.email,
+            name: &entry.name,
+            phone: &entry.phone,
+            locale: entry.locale,
+        },
+        &tickets,
+        None,
+    )
+    .await?;
+    waitlist::mark_claimed(&mut *tx, entry.id, &order.id).await?;
+
+    tx.commit().await?;
+    request_payment(&state, &order);
+
+    Ok((StatusCode::CREATED, Json(order)))
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::<AppState>::new()
+        .route("/", get(list_waitlist).post(join_waitlist))
+        .route("/:id", get(get_entry))
+        .route("/:id/claim", post(claim_offer))
+}
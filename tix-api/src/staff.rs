@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// What a staff member is allowed to do.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "staff_role", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Full access, including managing events and other staff.
+    Admin,
+    /// Can scan tickets at the door.
+    Scanner,
+    /// Read-only access to orders and statistics.
+    Viewer,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Staff {
+    pub email: String,
+    pub role: Role,
+    pub name: Option<String>,
+    pub invited_by: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+pub async fn get(executor: impl sqlx::PgExecutor<'_>, email: &str) -> sqlx::Result<Option<Staff>> {
+    sqlx::query_as!(
+        Staff,
+        r#"SELECT email, role AS "role: Role", name, invited_by, created_at FROM staff WHERE email = $1"#,
+        email.to_lowercase()
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+pub async fn list(executor: impl sqlx::PgExecutor<'_>) -> sqlx::Result<Vec<Staff>> {
+    sqlx::query_as!(
+        Staff,
+        r#"SELECT email, role AS "role: Role", name, invited_by, created_at FROM staff ORDER BY email"#
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Add or change the role of a staff member.
+pub async fn upsert(
+    executor: impl sqlx::PgExecutor<'_>,
+    email: &str,
+    role: Role,
+    invited_by: Option<&str>,
+) -> sqlx::Result<Staff> {
+    sqlx::query_as!(
+        Staff,
+        r#"INSERT INTO staff (email, role, invited_by) VALUES ($1, $2, $3)
+    ON CONFLICT (email) DO UPDATE SET role = EXCLUDED.role
+    RETURNING email, role AS "role: Role", name, invited_by, created_at"#,
+        email.to_lowercase(),
+        role as Role,
+        invited_by,
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Make sure everyone in `emails` is an admin. Used to bootstrap access on
+/// a fresh database.
+pub async fn ensure_admins(pool: &sqlx::PgPool, emails: &[String]) -> sqlx::Result<()> {
+    for email in emails.iter().filter(|email| !email.trim().is_empty()) {
+        upsert(pool, email.trim(), Role::Admin, None).await?;
+    }
+
+    Ok(())
+}
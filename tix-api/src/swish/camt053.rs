@@ -0,0 +1,216 @@
+//! ISO 20022 bank statements (camt.053), which most Swedish banks can export
+//! for the account the Swish number is connected to.
+
+use std::fmt::Display;
+
+use roxmltree::{Document, Node};
+use rust_decimal::Decimal;
+use time::{
+    format_description::{well_known::Rfc3339, FormatItem},
+    macros::format_description,
+    Date, OffsetDateTime, PrimitiveDateTime,
+};
+use time_tz::PrimitiveDateTimeExt;
+
+use super::{Part, ReportError, ReportFormat, Transaction};
+
+const DATE: &[FormatItem<'_>] = format_description!("[year]-[month]-[day]");
+const DATE_TIME: &[FormatItem<'_>] =
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second][optional [.[subsecond]]]");
+
+pub struct Camt053;
+
+impl ReportFormat for Camt053 {
+    fn name(&self) -> &'static str {
+        "camt053"
+    }
+
+    fn detect(&self, input: &[u8]) -> bool {
+        // the namespace is declared on the root element
+        let start = &input[..input.len().min(1024)];
+        start.windows(8).any(|w| w == b"camt.053")
+    }
+
+    fn parse(&self, input: &[u8]) -> Result<Vec<Transaction>, ReportError> {
+        let input = std::str::from_utf8(input).map_err(|e| ReportError {
+            row: None,
+            column: None,
+            message: e.to_string(),
+        })?;
+        let doc = Document::parse(input).map_err(|e| ReportError {
+            row: Some(e.pos().row.into()),
+            column: None,
+            message: e.to_string(),
+        })?;
+
+        let mut transactions = Vec::new();
+
+        for stmt in doc.descendants().filter(|n| n.has_tag_name("Stmt")) {
+            let account = path(stmt, &["Acct", "Id"]);
+            let payee = Part {
+                phone: account
+                    .and_then(|id| text(id, &["Othr", "Id"]).or_else(|| text(id, &["IBAN"])))
+                    .unwrap_or_default()
+                    .to_owned(),
+                name: text(stmt, &["Acct", "Ownr", "Nm"])
+                    .unwrap_or_default()
+                    .to_owned(),
+            };
+
+            for entry in stmt.children().filter(|n| n.has_tag_name("Ntry")) {
+                Entry { doc: &doc, entry }.transactions(&payee, &mut transactions)?;
+            }
+        }
+
+        Ok(transactions)
+    }
+}
+
+/// The element at `path` below `node`.
+fn path<'a, 'i>(node: Node<'a, 'i>, path: &[&str]) -> Option<Node<'a, 'i>> {
+    path.iter().try_fold(node, |node, name| {
+        node.children().find(|n| n.has_tag_name(*name))
+    })
+}
+
+fn text<'a>(node: Node<'a, '_>, p: &[&str]) -> Option<&'a str> {
+    path(node, p)
+        .and_then(|n| n.text())
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+}
+
+fn parse_time(s: &str) -> Result<OffsetDateTime, time::error::Parse> {
+    let stockholm = time_tz::timezones::db::europe::STOCKHOLM;
+
+    OffsetDateTime::parse(s, &Rfc3339)
+        .or_else(|_| {
+            PrimitiveDateTime::parse(s, DATE_TIME).map(|t| t.assume_timezone_utc(stockholm))
+        })
+        .or_else(|_| Date::parse(s, DATE).map(|d| d.midnight().assume_timezone_utc(stockholm)))
+}
+
+/// One `Ntry`, which may hold the details of several transactions.
+struct Entry<'a, 'i> {
+    doc: &'a Document<'i>,
+    entry: Node<'a, 'i>,
+}
+
+impl<'a, 'i> Entry<'a, 'i> {
+    fn error(&self, node: Node, message: impl Display) -> ReportError {
+        ReportError {
+            row: Some(self.doc.text_pos_at(node.range().start).row.into()),
+            column: Some(node.tag_name().name().to_owned()),
+            message: message.to_string(),
+        }
+    }
+
+    fn required(&self, node: Node<'a, 'i>, p: &[&str]) -> Result<Node<'a, 'i>, ReportError> {
+        path(node, p).ok_or_else(|| self.error(node, format!("missing {}", p.join("/"))))
+    }
+
+    fn amount(&self, amt: Node, credit: bool) -> Result<Decimal, ReportError> {
+        match amt.attribute("Ccy") {
+            Some("SEK") | None => {}
+            Some(currency) => {
+                return Err(self.error(amt, format!("unsupported currency {currency}")))
+            }
+        }
+
+        let amount = amt
+            .text()
+            .unwrap_or_default()
+            .trim()
+            .parse::<Decimal>()
+            .map_err(|e| self.error(amt, e))?;
+
+        Ok(if credit { amount } else { -amount })
+    }
+
+    fn time(&self, details: Option<Node<'a, 'i>>) -> Result<OffsetDateTime, ReportError> {
+        let node = details
+            .and_then(|d| path(d, &["RltdDts", "AccptncDtTm"]))
+            .or_else(|| path(self.entry, &["BookgDt", "DtTm"]))
+            .or_else(|| path(self.entry, &["BookgDt", "Dt"]))
+            .or_else(|| path(self.entry, &["ValDt", "Dt"]))
+            .ok_or_else(|| self.error(self.entry, "missing BookgDt"))?;
+
+        parse_time(node.text().unwrap_or_default().trim()).map_err(|e| self.error(node, e))
+    }
+
+    fn transactions(
+        &self,
+        payee: &Part,
+        transactions: &mut Vec<Transaction>,
+    ) -> Result<(), ReportError> {
+        let indicator = self.required(self.entry, &["CdtDbtInd"])?;
+        let credit = match indicator.text().map(str::trim) {
+            Some("CRDT") => true,
+            Some("DBIT") => false,
+            _ => return Err(self.error(indicator, "expected CRDT or DBIT")),
+        };
+        let entry_amount = self.required(self.entry, &["Amt"])?;
+
+        let details = path(self.entry, &["NtryDtls"])
+            .into_iter()
+            .flat_map(|d| d.children().filter(|n| n.has_tag_name("TxDtls")))
+            .collect::<Vec<_>>();
+
+        if details.is_empty() {
+            transactions.push(Transaction {
+                time: self.time(None)?,
+                amount: self.amount(entry_amount, credit)?,
+                message: text(self.entry, &["AddtlNtryInf"])
+                    .unwrap_or_default()
+                    .to_owned(),
+                payor: Part {
+                    phone: String::new(),
+                    name: String::new(),
+                },
+                payee: Part {
+                    phone: payee.phone.clone(),
+                    name: payee.name.clone(),
+                },
+            });
+
+            return Ok(());
+        }
+
+        for tx in details {
+            // batched entries have the amount of each transaction in the details
+            let amount = match path(tx, &["Amt"]).or_else(|| path(tx, &["AmtDtls", "TxAmt", "Amt"]))
+            {
+                Some(amt) => self.amount(amt, credit)?,
+                None => self.amount(entry_amount, credit)?,
+            };
+            let parties = path(tx, &["RltdPties"]);
+            let debtor = parties.and_then(|p| path(p, &["Dbtr"]));
+
+            transactions.push(Transaction {
+                time: self.time(Some(tx))?,
+                amount,
+                message: text(tx, &["RmtInf", "Ustrd"])
+                    .or_else(|| text(tx, &["AddtlTxInf"]))
+                    .or_else(|| text(self.entry, &["AddtlNtryInf"]))
+                    .unwrap_or_default()
+                    .to_owned(),
+                payor: Part {
+                    phone: parties
+                        .and_then(|p| text(p, &["DbtrAcct", "Id", "Othr", "Id"]))
+                        .unwrap_or_default()
+                        .to_owned(),
+                    name: debtor
+                        .and_then(|d| text(d, &["Nm"]).or_else(|| text(d, &["Pty", "Nm"])))
+                        .unwrap_or_default()
+                        .to_owned(),
+                },
+                payee: Part {
+                    phone: payee.phone.clone(),
+                    name: payee.name.clone(),
+                },
+            });
+        }
+
+        Ok(())
+    }
+}
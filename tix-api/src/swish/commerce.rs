@@ -0,0 +1,481 @@
+//! Payment requests through the Swish Commerce API ("Swish Handel"), so that
+//! payments are registered as soon as they are made instead of when the next
+//! report is imported.
+//!
+//! Swish calls back when a request is paid or fails. The callback is only
+//! used as a hint: the status is always fetched from the API, which requires
+//! the merchant certificate, and requests that never get a callback are
+//! polled. Requests of orders that are canceled or expire are canceled, so
+//! that they cannot be paid by mistake.
+
+use reqwest::header::CONTENT_TYPE;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    error::{Code, ResponseError},
+    history::OrderEventKind,
+    order::{self, Change, Order, OrderError, OrderId, OrderStatus},
+    payment::{self, NewAllocation, PaymentMethod},
+};
+
+/// How often unresolved payment requests are polled.
+const INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct CommerceConfig {
+    /// Base URL of the API, e.g. `https://cpc.getswish.net/swish-cpcapi`.
+    pub api_url: String,
+    /// Swish number payments are made to, digits only.
+    pub payee_alias: String,
+    /// Public URL that Swish posts status updates to.
+    pub callback_url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "swish_request_status", rename_all = "snake_case")]
+#[serde(rename_all = "UPPERCASE")]
+pub enum RequestStatus {
+    Created,
+    Paid,
+    Declined,
+    Error,
+    Cancelled,
+}
+
+/// An error reported by the API.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiError {
+    pub error_code: String,
+    #[serde(default)]
+    pub error_message: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CommerceError {
+    #[error("swish request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("swish rejected the request: {}", .0.iter().map(|e| format!("{} {}", e.error_code, e.error_message)).collect::<Vec<_>>().join(", "))]
+    Rejected(Vec<ApiError>),
+    #[error("swish returned {0}")]
+    Status(reqwest::StatusCode),
+    #[error("payment request not found")]
+    NotFound,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Order(#[from] OrderError),
+}
+
+impl From<CommerceError> for ResponseError {
+    fn from(e: CommerceError) -> Self {
+        match e {
+            CommerceError::NotFound => Self::new(Code::PaymentNotFound, e.to_string()),
+            CommerceError::Database(e) => e.into(),
+            CommerceError::Order(e) => e.into(),
+            e => {
+                error!(?e);
+                Self::new(Code::InternalError, "swish error")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NewRequest<'a> {
+    payee_payment_reference: &'a str,
+    callback_url: &'a str,
+    payee_alias: &'a str,
+    /// Sends the request straight to the payer's app. Without it, the
+    /// returned token is needed to open the app.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payer_alias: Option<&'a str>,
+    amount: String,
+    currency: &'static str,
+    message: &'a str,
+}
+
+/// A payment request as reported by Swish, both when fetched and in
+/// callbacks.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentRequestState {
+    pub id: String,
+    pub payment_reference: Option<String>,
+    pub payer_alias: Option<String>,
+    pub amount: Decimal,
+    pub status: RequestStatus,
+    #[serde(default, with = "time::serde::iso8601::option")]
+    pub date_paid: Option<OffsetDateTime>,
+    pub error_code: Option<String>,
+    pub error_message: Option<String>,
+}
+
+pub struct Client {
+    http: reqwest::Client,
+    config: CommerceConfig,
+}
+
+impl Client {
+    pub fn new(http: reqwest::Client, config: CommerceConfig) -> Self {
+        Self { http, config }
+    }
+
+    /// A client authenticating with the merchant certificate, given as
+    /// PKCS #12. The test environment is signed by its own root, which can
+    /// be added as PEM.
+    pub fn with_certificate(
+        config: CommerceConfig,
+        pkcs12: &[u8],
+        password: &str,
+        root: Option<&[u8]>,
+    ) -> reqwest::Result<Self> {
+        let mut builder = reqwest::Client::builder()
+            .identity(reqwest::Identity::from_pkcs12_der(pkcs12, password)?);
+        if let Some(root) = root {
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(root)?);
+        }
+
+        Ok(Self::new(builder.build()?, config))
+    }
+
+    /// The error of an unsuccessful response, which lists what was wrong
+    /// with the request if it was invalid.
+    async fn rejected(response: reqwest::Response) -> CommerceError {
+        if response.status() != reqwest::StatusCode::UNPROCESSABLE_ENTITY {
+            return CommerceError::Status(response.status());
+        }
+
+        match response.json().await {
+            Ok(errors) => CommerceError::Rejected(errors),
+            Err(e) => e.into(),
+        }
+    }
+
+    /// Create a payment request with our own `id`, which has to be 32
+    /// uppercase hexadecimal digits. Returns the token for opening the
+    /// Swish app, if there is no `payer_alias`.
+    pub async fn create(
+        &self,
+        id: &str,
+        order_id: &OrderId,
+        amount: i32,
+        payer_alias: Option<&str>,
+    ) -> Result<Option<String>, CommerceError> {
+        let response = self
+            .http
+            .put(format!(
+                "{}/api/v2/paymentrequests/{id}",
+                self.config.api_url
+            ))
+            .json(&NewRequest {
+                payee_payment_reference: order_id.as_ref(),
+                callback_url: &self.config.callback_url,
+                payee_alias: &self.config.payee_alias,
+                payer_alias,
+                amount: format!("{amount}.00"),
+                currency: "SEK",
+                message: order_id.as_ref(),
+            })
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Self::rejected(response).await);
+        }
+
+        Ok(response
+            .headers()
+            .get("PaymentRequestToken")
+            .and_then(|token| token.to_str().ok())
+            .map(ToOwned::to_owned))
+    }
+
+    /// Cancel a request that has not been paid yet. Returns its new state.
+    pub async fn cancel(&self, id: &str) -> Result<PaymentRequestState, CommerceError> {
+        let patch = json!([{ "op": "replace", "path": "/status", "value": "cancelled" }]);
+        let response = self
+            .http
+            .patch(format!(
+                "{}/api/v1/paymentrequests/{id}",
+                self.config.api_url
+            ))
+            .header(CONTENT_TYPE, "application/json-patch+json")
+            .body(patch.to_string())
+            .send()
+            .await?;
+
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Err(CommerceError::NotFound),
+            status if status.is_success() => Ok(response.json().await?),
+            _ => Err(Self::rejected(response).await),
+        }
+    }
+
+    pub async fn get(&self, id: &str) -> Result<PaymentRequestState, CommerceError> {
+        let response = self
+            .http
+            .get(format!(
+                "{}/api/v1/paymentrequests/{id}",
+                self.config.api_url
+            ))
+            .send()
+            .await?;
+
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Err(CommerceError::NotFound),
+            status if status.is_success() => Ok(response.json().await?),
+            _ => Err(Self::rejected(response).await),
+        }
+    }
+}
+
+/// The Swish alias of a Swedish mobile number, e.g. `46701234567` for
+/// `070-123 45 67`. Other numbers cannot receive payment requests.
+pub fn payer_alias(phone: &str) -> Option<String> {
+    let digits = phone
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>();
+    let national = if phone.trim_start().starts_with('+') {
+        digits.strip_prefix("46")?
+    } else if let Some(rest) = digits.strip_prefix("0046") {
+        rest
+    } else {
+        digits.strip_prefix('0')?
+    };
+
+    (national.len() == 9 && national.starts_with('7')).then(|| format!("46{national}"))
+}
+
+/// Ask Swish for a payment of `order`. Failures are stored with the request,
+/// since the order can still be paid by typing in the details.
+pub async fn request_payment(
+    pool: &PgPool,
+    client: &Client,
+    order: &Order,
+) -> Result<(), CommerceError> {
+    let id = Uuid::new_v4().simple().to_string().to_uppercase();
+
+    sqlx::query!(
+        "INSERT INTO swish_payment_requests (id, order_id, amount) VALUES ($1, $2, $3)",
+        id,
+        order.id.as_ref(),
+        Decimal::from(order.amount),
+    )
+    .execute(pool)
+    .await?;
+
+    let payer_alias = payer_alias(&order.phone);
+    match client
+        .create(&id, &order.id, order.amount, payer_alias.as_deref())
+        .await
+    {
+        Ok(token) => {
+            sqlx::query!(
+                "UPDATE swish_payment_requests SET token = $2, updated_at = NOW() WHERE id = $1",
+                id,
+                token,
+            )
+            .execute(pool)
+            .await?;
+
+            Ok(())
+        }
+        Err(e) => {
+            sqlx::query!(
+                "UPDATE swish_payment_requests SET status = 'error', error_message = $2, updated_at = NOW() WHERE id = $1",
+                id,
+                e.to_string(),
+            )
+            .execute(pool)
+            .await?;
+
+            Err(e)
+        }
+    }
+}
+
+/// Store the status reported by Swish. A paid request is allocated to its
+/// order, which is marked as paid if that covers it. Requests that have
+/// already been resolved are left alone. Returns whether anything changed.
+pub async fn update(pool: &PgPool, state: &PaymentRequestState) -> Result<bool, OrderError> {
+    if state.status == RequestStatus::Created {
+        return Ok(false);
+    }
+
+    let mut tx = pool.begin().await?;
+    let paid_at = state.date_paid.unwrap_or_else(OffsetDateTime::now_utc);
+
+    let Some(order_id) = sqlx::query_scalar!(
+        "UPDATE swish_payment_requests
+    SET status = $2, payment_reference = $3, payer_alias = $4, error_code = $5, error_message = $6, paid_at = $7, updated_at = NOW()
+    WHERE id = $1 AND status = 'created'
+    RETURNING order_id",
+        state.id,
+        state.status as RequestStatus,
+        state.payment_reference,
+        state.payer_alias,
+        state.error_code,
+        state.error_message,
+        (state.status == RequestStatus::Paid).then_some(paid_at),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(false);
+    };
+    let order_id = OrderId::from(order_id);
+
+    if state.status == RequestStatus::Paid {
+        let order = order::lock(&mut *tx, &order_id)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)?;
+        payment::allocate(
+            &mut *tx,
+            NewAllocation {
+                payment_id: None,
+                order_id: &order_id,
+                amount: state.amount,
+                method: PaymentMethod::Swish,
+                note: state.payment_reference.as_deref(),
+                created_by: None,
+            },
+        )
+        .await?;
+        order::refresh_paid(
+            &mut tx,
+            &order,
+            paid_at,
+            Change::new(OrderEventKind::PaymentAdded, None),
+        )
+        .await?;
+        info!(%order_id, "paid through swish");
+    }
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Fetch the status of one of our payment requests and store it.
+pub async fn sync(pool: &PgPool, client: &Client, id: &str) -> Result<bool, CommerceError> {
+    let exists = sqlx::query_scalar!(
+        "SELECT EXISTS (SELECT 1 FROM swish_payment_requests WHERE id = $1)",
+        id
+    )
+    .fetch_one(pool)
+    .await?;
+    if exists != Some(true) {
+        return Err(CommerceError::NotFound);
+    }
+
+    let state = client.get(id).await?;
+    Ok(update(pool, &state).await?)
+}
+
+/// Cancel one of our payment requests and store its new status.
+pub async fn cancel(pool: &PgPool, client: &Client, id: &str) -> Result<bool, CommerceError> {
+    let state = client.cancel(id).await?;
+    Ok(update(pool, &state).await?)
+}
+
+/// The token for opening the unresolved request for `order_id` in the Swish
+/// app, if there is one.
+pub async fn open_token(
+    executor: impl sqlx::PgExecutor<'_>,
+    order_id: &OrderId,
+) -> sqlx::Result<Option<String>> {
+    Ok(sqlx::query_scalar!(
+        "SELECT token FROM swish_payment_requests
+    WHERE order_id = $1 AND status = 'created' AND token IS NOT NULL
+    ORDER BY created_at DESC
+    LIMIT 1",
+        order_id.as_ref(),
+    )
+    .fetch_optional(executor)
+    .await?
+    .flatten())
+}
+
+/// Cancel the unresolved requests for `order_id`, e.g. when the order is
+/// canceled. Returns the number of requests canceled.
+pub async fn cancel_for_order(
+    pool: &PgPool,
+    client: &Client,
+    order_id: &OrderId,
+) -> Result<usize, CommerceError> {
+    let ids = sqlx::query_scalar!(
+        "SELECT id FROM swish_payment_requests WHERE order_id = $1 AND status = 'created'",
+        order_id.as_ref(),
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut count = 0;
+    for id in ids {
+        if cancel(pool, client, &id).await? {
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Sync every request from the last day that Swish has not resolved yet, and
+/// cancel those whose orders have been closed in the meantime, e.g. by
+/// expiring.
+pub async fn poll(pool: &PgPool, client: &Client) -> sqlx::Result<usize> {
+    let requests = sqlx::query!(
+        r#"SELECT r.id, o.status AS "order_status: OrderStatus"
+    FROM swish_payment_requests r
+    JOIN orders o ON o.id = r.order_id
+    WHERE r.status = 'created' AND r.created_at > NOW() - INTERVAL '1 day'
+    ORDER BY r.created_at"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut count = 0;
+    for request in requests {
+        let id = request.id;
+        let result = if request.order_status.is_canceled() {
+            cancel(pool, client, &id).await
+        } else {
+            sync(pool, client, &id).await
+        };
+
+        match result {
+            Ok(true) => count += 1,
+            Ok(false) => {}
+            Err(CommerceError::Database(e) | CommerceError::Order(OrderError::Database(e))) => {
+                return Err(e)
+            }
+            Err(e) => error!(id, "cannot poll payment request: {e}"),
+        }
+    }
+
+    Ok(count)
+}
+
+/// Periodically poll unresolved payment requests. Never returns.
+pub async fn run(pool: PgPool, client: std::sync::Arc<Client>) {
+    let mut interval = tokio::time::interval(INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match poll(&pool, &client).await {
+            Ok(0) => {}
+            Ok(count) => info!("resolved {count} swish payment requests"),
+            Err(err) => error!("swish polling failed: {err}"),
+        }
+    }
+}
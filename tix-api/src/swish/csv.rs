@@ -0,0 +1,237 @@
+//! CSV reports, both the one exported from the Swish portal and the ones
+//! banks offer. They share the Swedish column names but differ in encoding,
+//! delimiter and decimal separator.
+
+use std::fmt::Display;
+
+use ::csv::StringRecord;
+use rust_decimal::Decimal;
+use time::{format_description::FormatItem, macros::format_description, Date, Time};
+use time_tz::PrimitiveDateTimeExt;
+
+use super::{Part, ReportError, ReportFormat, Transaction};
+
+const DATE: &[FormatItem<'_>] = format_description!("[year]-[month]-[day]");
+const TIME: &[FormatItem<'_>] = format_description!("[hour]:[minute][optional [:[second]]]");
+
+/// Columns that every report must have.
+const COLUMNS: &[&str] = &[
+    "Transdag",
+    "Mottagarnr",
+    "Mottagarnamn",
+    "Avsändarnr",
+    "Avsändarnamn",
+    "Meddelande",
+    "Tid",
+    "Belopp",
+];
+
+/// The report from the Swish portal: Windows-1252, or UTF-8 with a byte order
+/// mark, comma separated, with an optional first line starting with `*`.
+pub struct SwishCsv;
+
+/// Bank exports: UTF-8, semicolon separated and with decimal commas.
+pub struct BankCsv;
+
+struct Dialect {
+    delimiter: u8,
+    decimal_comma: bool,
+}
+
+/// The line with the column names, skipping a byte order mark and the
+/// summary line of Swish reports.
+///
+/// `* Swish-rapport Avser 2024-01-01 - 2024-01-10 Skapad 2024-01-10 12:23 CET`
+fn header_line(input: &[u8]) -> &[u8] {
+    let input = input.strip_prefix(b"\xef\xbb\xbf").unwrap_or(input);
+    let mut lines = input.split(|b| *b == b'\n');
+    let first = lines.next().unwrap_or_default();
+
+    if first.starts_with(b"*") {
+        lines.next().unwrap_or_default()
+    } else {
+        first
+    }
+}
+
+fn count(line: &[u8], byte: u8) -> usize {
+    line.iter().filter(|b| **b == byte).count()
+}
+
+fn has_amount_column(line: &[u8]) -> bool {
+    line.windows(6).any(|w| w == b"Belopp")
+}
+
+impl ReportFormat for SwishCsv {
+    fn name(&self) -> &'static str {
+        "swish_csv"
+    }
+
+    fn detect(&self, input: &[u8]) -> bool {
+        let header = header_line(input);
+        has_amount_column(header) && count(header, b',') > count(header, b';')
+    }
+
+    fn parse(&self, input: &[u8]) -> Result<Vec<Transaction>, ReportError> {
+        // reports saved again as UTF-8 start with a byte order mark
+        let s = match input.strip_prefix(b"\xef\xbb\xbf") {
+            Some(utf8) => String::from_utf8_lossy(utf8),
+            None => {
+                encoding_rs::WINDOWS_1252
+                    .decode_without_bom_handling(input)
+                    .0
+            }
+        };
+
+        read(
+            &s,
+            Dialect {
+                delimiter: b',',
+                decimal_comma: false,
+            },
+        )
+    }
+}
+
+impl ReportFormat for BankCsv {
+    fn name(&self) -> &'static str {
+        "bank_csv"
+    }
+
+    fn detect(&self, input: &[u8]) -> bool {
+        let header = header_line(input);
+        std::str::from_utf8(input).is_ok()
+            && has_amount_column(header)
+            && count(header, b';') > count(header, b',')
+    }
+
+    fn parse(&self, input: &[u8]) -> Result<Vec<Transaction>, ReportError> {
+        let s = std::str::from_utf8(input).map_err(|e| ReportError {
+            row: None,
+            column: None,
+            message: e.to_string(),
+        })?;
+
+        read(
+            s.trim_start_matches('\u{feff}'),
+            Dialect {
+                delimiter: b';',
+                decimal_comma: true,
+            },
+        )
+    }
+}
+
+/// One line of the report, with cells looked up by header.
+struct Record<'a> {
+    headers: &'a StringRecord,
+    record: &'a StringRecord,
+    line: u64,
+    dialect: &'a Dialect,
+}
+
+impl Record<'_> {
+    fn error(&self, column: &str, message: impl Display) -> ReportError {
+        ReportError {
+            row: Some(self.line),
+            column: Some(column.to_owned()),
+            message: message.to_string(),
+        }
+    }
+
+    fn get(&self, column: &str) -> Result<&str, ReportError> {
+        self.headers
+            .iter()
+            .position(|h| h == column)
+            .and_then(|i| self.record.get(i))
+            .ok_or_else(|| self.error(column, "missing value"))
+    }
+
+    fn parse<T, E: Display>(
+        &self,
+        column: &str,
+        f: impl FnOnce(&str) -> Result<T, E>,
+    ) -> Result<T, ReportError> {
+        f(self.get(column)?.trim()).map_err(|e| self.error(column, e))
+    }
+
+    fn amount(&self, s: &str) -> Result<Decimal, rust_decimal::Error> {
+        if self.dialect.decimal_comma {
+            // e.g. "1 234,50", sometimes with a non-breaking space
+            s.chars()
+                .filter(|c| !c.is_whitespace())
+                .map(|c| if c == ',' { '.' } else { c })
+                .collect::<String>()
+                .parse()
+        } else {
+            s.parse()
+        }
+    }
+
+    fn transaction(&self) -> Result<Transaction, ReportError> {
+        let date = self.parse("Transdag", |s| Date::parse(s, DATE))?;
+        let time = self.parse("Tid", |s| Time::parse(s, TIME))?;
+
+        Ok(Transaction {
+            time: date
+                .with_time(time)
+                .assume_timezone_utc(time_tz::timezones::db::europe::STOCKHOLM),
+            amount: self.parse("Belopp", |s| self.amount(s))?,
+            message: self.get("Meddelande")?.to_owned(),
+            payor: Part {
+                phone: self.get("Avsändarnr")?.to_owned(),
+                name: self.get("Avsändarnamn")?.to_owned(),
+            },
+            payee: Part {
+                phone: self.get("Mottagarnr")?.to_owned(),
+                name: self.get("Mottagarnamn")?.to_owned(),
+            },
+        })
+    }
+}
+
+fn csv_error(e: ::csv::Error, skipped_lines: u64) -> ReportError {
+    ReportError {
+        row: e.position().map(|p| p.line() + skipped_lines),
+        column: None,
+        message: e.to_string(),
+    }
+}
+
+fn read(input: &str, dialect: Dialect) -> Result<Vec<Transaction>, ReportError> {
+    let (skipped_lines, csv) = match input.split_once('\n') {
+        Some((_, after)) if input.starts_with('*') => (1, after),
+        _ => (0, input),
+    };
+
+    let mut reader = ::csv::ReaderBuilder::new()
+        .delimiter(dialect.delimiter)
+        .trim(::csv::Trim::Headers)
+        .from_reader(csv.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| csv_error(e, skipped_lines))?
+        .clone();
+
+    if let Some(missing) = COLUMNS.iter().find(|c| !headers.iter().any(|h| h == **c)) {
+        return Err(ReportError {
+            row: Some(skipped_lines + 1),
+            column: Some((*missing).to_owned()),
+            message: "missing column".to_owned(),
+        });
+    }
+
+    reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| csv_error(e, skipped_lines))?;
+            Record {
+                headers: &headers,
+                record: &record,
+                line: record.position().map_or(0, |p| p.line()) + skipped_lines,
+                dialect: &dialect,
+            }
+            .transaction()
+        })
+        .collect()
+}
@@ -0,0 +1,97 @@
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::error::{Code, ResponseError};
+
+pub mod camt053;
+pub mod commerce;
+pub mod csv;
+pub mod request;
+
+/// A Swish number the way the app and the API want it, digits only.
+pub fn alias(number: &str) -> String {
+    number.chars().filter(char::is_ascii_digit).collect()
+}
+
+/// A report that could not be parsed, pointing at the offending cell when
+/// possible.
+#[derive(Debug, thiserror::Error, Serialize)]
+#[error("{message}")]
+pub struct ReportError {
+    /// 1-based line in the uploaded file.
+    pub row: Option<u64>,
+    /// Header of the column that could not be parsed.
+    pub column: Option<String>,
+    #[serde(skip)]
+    pub message: String,
+}
+
+impl From<ReportError> for ResponseError {
+    fn from(e: ReportError) -> Self {
+        let message = match (&e.row, &e.column) {
+            (Some(row), Some(column)) => format!("row {row}, column {column}: {}", e.message),
+            (Some(row), None) => format!("row {row}: {}", e.message),
+            _ => e.message.clone(),
+        };
+
+        Self::new(Code::InvalidSwishReport, message).with_details(e)
+    }
+}
+
+/// An upload that does not contain a report at all, with the same details
+/// as a [`ReportError`] so that clients handle both the same way.
+pub fn invalid_upload(message: impl Into<String>) -> ResponseError {
+    let message = message.into();
+
+    ResponseError::new(Code::InvalidUpload, message.clone()).with_details(ReportError {
+        row: None,
+        column: None,
+        message,
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct Part {
+    pub phone: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Transaction {
+    #[serde(with = "time::serde::rfc3339")]
+    pub time: OffsetDateTime,
+    pub amount: rust_decimal::Decimal,
+    pub message: String,
+    /// The one who sent the money.
+    pub payor: Part,
+    /// The one who received the money.
+    pub payee: Part,
+}
+
+/// A source of Swish transactions.
+pub trait ReportFormat: Sync {
+    /// Identifies the format in logs and errors.
+    fn name(&self) -> &'static str;
+    /// Whether `input` looks like a report in this format. Only the start of
+    /// the report is looked at; parsing may still fail.
+    fn detect(&self, input: &[u8]) -> bool;
+    fn parse(&self, input: &[u8]) -> Result<Vec<Transaction>, ReportError>;
+}
+
+/// Supported formats, in the order they are tried.
+pub static FORMATS: &[&dyn ReportFormat] = &[&camt053::Camt053, &csv::BankCsv, &csv::SwishCsv];
+
+pub fn detect(input: &[u8]) -> Option<&'static dyn ReportFormat> {
+    FORMATS.iter().copied().find(|format| format.detect(input))
+}
+
+/// Parse a report in any of the supported [formats](FORMATS).
+pub fn parse_transactions(input: &[u8]) -> Result<Vec<Transaction>, ReportError> {
+    let format = detect(input).ok_or_else(|| ReportError {
+        row: None,
+        column: None,
+        message: "unrecognized report format".to_owned(),
+    })?;
+
+    format.parse(input)
+}
@@ -0,0 +1,66 @@
+//! Prefilled payments, so that customers do not have to type the payee,
+//! amount and order id into Swish themselves.
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::order::Order;
+
+use super::alias;
+
+#[derive(Debug, Serialize)]
+pub struct PaymentRequest {
+    /// Swish number, digits only.
+    pub payee: String,
+    pub amount: i32,
+    /// The order id, which cannot be changed in the app.
+    pub message: String,
+    /// Opens the Swish app with the payment filled in.
+    pub url: String,
+    /// Opens the request sent through the [Swish API](super::commerce), if
+    /// there is one, which registers the payment as soon as it is made.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_url: Option<String>,
+    /// Contents of a QR code that can be scanned from the Swish app.
+    pub qr_data: String,
+}
+
+impl PaymentRequest {
+    /// A payment of `order` to the Swish number `payee`.
+    pub fn for_order(payee: &str, order: &Order) -> Self {
+        let payee = alias(payee);
+        let message = order.id.to_string();
+
+        let data = json!({
+            "version": 1,
+            "payee": { "value": payee },
+            "amount": { "value": order.amount },
+            "message": { "value": message, "editable": false },
+        });
+        let url = format!(
+            "swish://payment?{}",
+            serde_urlencoded::to_string([("data", data.to_string())]).unwrap()
+        );
+        // C for prefilled, and a mask of the editable fields last, none here
+        let qr_data = format!("C{payee};{};{message};0", order.amount);
+
+        Self {
+            payee,
+            amount: order.amount,
+            message,
+            url,
+            request_url: None,
+            qr_data,
+        }
+    }
+
+    /// Open the payment request with `token` instead of prefilling a new
+    /// payment, where the app allows it.
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.request_url = Some(format!(
+            "swish://paymentrequest?{}",
+            serde_urlencoded::to_string([("token", token)]).unwrap()
+        ));
+        self
+    }
+}
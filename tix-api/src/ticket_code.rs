@@ -0,0 +1,98 @@
+//! Signed ticket codes that door scanners can verify without a network
+//! connection, using only the public key from `GET /tickets/key`.
+//!
+//! A code is `<payload>.<signature>`, both base64url without padding. The
+//! payload is the ticket id (16 bytes), the event id (16 bytes) and the
+//! order id (the remaining bytes) concatenated, and the signature is an
+//! Ed25519 signature over the raw payload.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    error::{Code, ResponseError},
+    order::OrderId,
+};
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct TicketClaims {
+    pub ticket_id: Uuid,
+    pub event_id: Uuid,
+    pub order_id: OrderId,
+}
+
+impl TicketClaims {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32 + self.order_id.as_ref().len());
+        bytes.extend_from_slice(self.ticket_id.as_bytes());
+        bytes.extend_from_slice(self.event_id.as_bytes());
+        bytes.extend_from_slice(self.order_id.as_ref().as_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() <= 32 {
+            return None;
+        }
+
+        let (ticket_id, rest) = bytes.split_at(16);
+        let (event_id, order_id) = rest.split_at(16);
+
+        Some(Self {
+            ticket_id: Uuid::from_slice(ticket_id).ok()?,
+            event_id: Uuid::from_slice(event_id).ok()?,
+            order_id: String::from_utf8(order_id.to_vec()).ok()?.into(),
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid ticket code")]
+pub struct InvalidCode;
+
+impl From<InvalidCode> for ResponseError {
+    fn from(value: InvalidCode) -> Self {
+        Self::new(Code::InvalidTicketCode, value.to_string())
+    }
+}
+
+pub struct TicketSigner {
+    key: SigningKey,
+}
+
+impl TicketSigner {
+    pub fn new(secret: &[u8; 32]) -> Self {
+        Self {
+            key: SigningKey::from_bytes(secret),
+        }
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    pub fn sign(&self, claims: &TicketClaims) -> String {
+        let payload = claims.to_bytes();
+        let signature = self.key.sign(&payload);
+
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&payload),
+            URL_SAFE_NO_PAD.encode(signature.to_bytes()),
+        )
+    }
+}
+
+/// Check the signature of `code` and return what it claims.
+pub fn verify(key: &VerifyingKey, code: &str) -> Result<TicketClaims, InvalidCode> {
+    let (payload, signature) = code.trim().split_once('.').ok_or(InvalidCode)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| InvalidCode)?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| InvalidCode)?;
+    let signature = Signature::from_slice(&signature).map_err(|_| InvalidCode)?;
+
+    key.verify(&payload, &signature).map_err(|_| InvalidCode)?;
+
+    TicketClaims::from_bytes(&payload).ok_or(InvalidCode)
+}
@@ -0,0 +1,454 @@
+//! Waitlist for sold out events.
+//!
+//! Customers who cannot get tickets sign up for a number of tickets of one
+//! type. When tickets are freed, e.g. by canceled or expired orders, they are
+//! held for the people who signed up first, and [`run`] offers them to them.
+//! Offered tickets are held for a limited time, during which they do not
+//! count as remaining, and are claimed through a link in the offer email.
+//! Unclaimed offers lapse and the tickets go to the next person on the list.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    email::{self, EmailConfig, EmailError, EmailKind},
+    error::{Code, ResponseError},
+    event::{self, EventError},
+    locale::Locale,
+    order::OrderId,
+    outbox,
+    routes::tickets::tickets_unoffered,
+};
+
+/// How often freed tickets are offered.
+const INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "waitlist_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum WaitlistStatus {
+    Waiting,
+    /// Tickets are held until the offer expires.
+    Offered,
+    Claimed,
+    /// The offer expired without being claimed.
+    Lapsed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WaitlistEntry {
+    pub id: Uuid,
+    pub event_id: Uuid,
+    pub ticket_type_id: Uuid,
+    pub count: i32,
+    pub email: String,
+    pub name: String,
+    pub phone: String,
+    pub locale: Locale,
+    pub status: WaitlistStatus,
+    /// Secret part of the claim link.
+    #[serde(skip)]
+    pub token: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub offered_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub offer_expires_at: Option<OffsetDateTime>,
+    /// The order the offer was claimed with.
+    pub order_id: Option<OrderId>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WaitlistError {
+    #[error("waitlist entry not found")]
+    NotFound,
+    #[error("the offer has expired")]
+    OfferExpired,
+    #[error("no tickets have been offered")]
+    NotOffered,
+    #[error("the offer has already been claimed")]
+    AlreadyClaimed,
+    #[error("there are tickets left to order")]
+    TicketsAvailable,
+    #[error("tickets have already been offered")]
+    AlreadyOffered,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl From<WaitlistError> for ResponseError {
+    fn from(e: WaitlistError) -> Self {
+        let code = match e {
+            WaitlistError::NotFound => Code::WaitlistEntryNotFound,
+            WaitlistError::OfferExpired | WaitlistError::NotOffered => Code::OfferUnavailable,
+            WaitlistError::AlreadyClaimed => Code::OfferClaimed,
+            WaitlistError::TicketsAvailable => Code::TicketsAvailable,
+            WaitlistError::AlreadyOffered => Code::AlreadyOffered,
+            WaitlistError::Database(e) => return e.into(),
+        };
+
+        Self::new(code, e.to_string())
+    }
+}
+
+pub struct NewEntry<'a> {
+    pub event_id: Uuid,
+    pub ticket_type_id: Uuid,
+    pub count: i32,
+    pub email: &'a str,
+    pub name: &'a str,
+    pub phone: &'a str,
+    pub locale: Locale,
+}
+
+/// Put someone last on the waitlist, returning the entry and whether it is
+/// new. Signing up again for the same ticket type while waiting changes the
+/// entry but keeps its place on the list, and fails once tickets have been
+/// offered.
+pub async fn join(
+    conn: &mut sqlx::PgConnection,
+    entry: NewEntry<'_>,
+) -> Result<(WaitlistEntry, bool), WaitlistError> {
+    let existing = sqlx::query_as!(
+        WaitlistEntry,
+        r#"SELECT
+      id,
+      event_id,
+      ticket_type_id,
+      count,
+      email,
+      name,
+      phone,
+      locale,
+      status AS "status: WaitlistStatus",
+      token,
+      offered_at,
+      offer_expires_at,
+      order_id AS "order_id: OrderId",
+      created_at
+    FROM waitlist
+    WHERE ticket_type_id = $1 AND LOWER(email) = LOWER($2) AND status IN ('waiting', 'offered')
+    FOR UPDATE"#,
+        entry.ticket_type_id,
+        entry.email,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    if let Some(existing) = existing {
+        // the offer is for the count signed up for
+        if existing.status == WaitlistStatus::Offered {
+            return Err(WaitlistError::AlreadyOffered);
+        }
+
+        let updated = sqlx::query_as!(
+            WaitlistEntry,
+            r#"UPDATE waitlist SET count = $2, name = $3, phone = $4, locale = $5
+    WHERE id = $1
+    RETURNING
+      id,
+      event_id,
+      ticket_type_id,
+      count,
+      email,
+      name,
+      phone,
+      locale,
+      status AS "status: WaitlistStatus",
+      token,
+      offered_at,
+      offer_expires_at,
+      order_id AS "order_id: OrderId",
+      created_at"#,
+            existing.id,
+            entry.count,
+            entry.name,
+            entry.phone,
+            entry.locale.as_str(),
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        return Ok((updated, false));
+    }
+
+    let created = sqlx::query_as!(
+        WaitlistEntry,
+        r#"INSERT INTO waitlist (id, event_id, ticket_type_id, count, email, name, phone, locale)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+    RETURNING
+      id,
+      event_id,
+      ticket_type_id,
+      count,
+      email,
+      name,
+      phone,
+      locale,
+      status AS "status: WaitlistStatus",
+      token,
+      offered_at,
+      offer_expires_at,
+      order_id AS "order_id: OrderId",
+      created_at"#,
+        Uuid::new_v4(),
+        entry.event_id,
+        entry.ticket_type_id,
+        entry.count,
+        entry.email,
+        entry.name,
+        entry.phone,
+        entry.locale.as_str(),
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok((created, true))
+}
+
+pub async fn get(
+    executor: impl sqlx::PgExecutor<'_>,
+    id: Uuid,
+) -> sqlx::Result<Option<WaitlistEntry>> {
+    sqlx::query_as!(
+        WaitlistEntry,
+        r#"SELECT
+      id,
+      event_id,
+      ticket_type_id,
+      count,
+      email,
+      name,
+      phone,
+      locale,
+      status AS "status: WaitlistStatus",
+      token,
+      offered_at,
+      offer_expires_at,
+      order_id AS "order_id: OrderId",
+      created_at
+    FROM waitlist
+    WHERE id = $1"#,
+        id,
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// The entry `id` if `token` is its claim token, locked until the end of
+/// the transaction.
+pub async fn get_with_token(
+    conn: &mut sqlx::PgConnection,
+    id: Uuid,
+    token: &str,
+) -> Result<WaitlistEntry, WaitlistError> {
+    let entry = sqlx::query_as!(
+        WaitlistEntry,
+        r#"SELECT
+      id,
+      event_id,
+      ticket_type_id,
+      count,
+      email,
+      name,
+      phone,
+      locale,
+      status AS "status: WaitlistStatus",
+      token,
+      offered_at,
+      offer_expires_at,
+      order_id AS "order_id: OrderId",
+      created_at
+    FROM waitlist
+    WHERE id = $1
+    FOR UPDATE"#,
+        id,
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    entry
+        .filter(|entry| entry.token.as_deref() == Some(token))
+        .ok_or(WaitlistError::NotFound)
+}
+
+/// Check that the offer of `entry` can still be claimed at `now`.
+pub fn check_claimable(entry: &WaitlistEntry, now: OffsetDateTime) -> Result<(), WaitlistError> {
+    match entry.status {
+        WaitlistStatus::Waiting => Err(WaitlistError::NotOffered),
+        WaitlistStatus::Claimed => Err(WaitlistError::AlreadyClaimed),
+        WaitlistStatus::Lapsed => Err(WaitlistError::OfferExpired),
+        WaitlistStatus::Offered if entry.offer_expires_at.is_some_and(|t| t <= now) => {
+            Err(WaitlistError::OfferExpired)
+        }
+        WaitlistStatus::Offered => Ok(()),
+    }
+}
+
+/// Mark the offer of entry `id` as claimed with `order_id`, releasing the hold
+/// on its tickets.
+pub async fn mark_claimed(
+    executor: impl sqlx::PgExecutor<'_>,
+    id: Uuid,
+    order_id: &OrderId,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE waitlist SET status = 'claimed', order_id = $2 WHERE id = $1",
+        id,
+        order_id.as_ref(),
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Offer the tickets remaining for `event` to the people waiting for them,
+/// in the order they signed up. People who want more tickets than there are
+/// are passed over, so that they do not hold up everyone behind them.
+/// Returns the number of offers made.
+async fn offer_event(
+    pool: &PgPool,
+    config: &EmailConfig,
+    event_id: Uuid,
+    claim_window: Duration,
+) -> Result<usize, EmailError> {
+    let mut tx = pool.begin().await?;
+
+    // the same lock as when ordering, so that offered tickets are not sold
+    let event = match event::lock(&mut *tx, event_id).await {
+        Ok(event) => event,
+        Err(EventError::Database(e)) => return Err(e.into()),
+        // deleted along with its waitlist
+        Err(_) => return Ok(0),
+    };
+    let now = OffsetDateTime::now_utc();
+    if !event.is_on_sale(now) {
+        return Ok(0);
+    }
+
+    let mut remaining = tickets_unoffered(&mut *tx, &event).await?;
+    if remaining.total == 0 {
+        return Ok(0);
+    }
+
+    let ticket_types = event::ticket_types(&mut *tx, event.id)
+        .await?
+        .into_iter()
+        .map(|t| (t.id, t.name))
+        .collect::<HashMap<_, _>>();
+
+    // in the same order as the tickets are held in tickets_remaining
+    let waiting = sqlx::query_scalar!(
+        "SELECT id FROM waitlist WHERE event_id = $1 AND status = 'waiting' ORDER BY created_at, id FOR UPDATE",
+        event.id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut count = 0;
+    for id in waiting {
+        let Some(entry) = get(&mut *tx, id).await? else {
+            continue;
+        };
+        let wanted = u32::try_from(entry.count).unwrap_or(u32::MAX);
+        if !remaining.take(entry.ticket_type_id, wanted) {
+            continue;
+        }
+
+        let entry = sqlx::query_as!(
+            WaitlistEntry,
+            r#"UPDATE waitlist
+    SET status = 'offered', token = $2, offered_at = $3, offer_expires_at = $4
+    WHERE id = $1
+    RETURNING
+      id,
+      event_id,
+      ticket_type_id,
+      count,
+      email,
+      name,
+      phone,
+      locale,
+      status AS "status: WaitlistStatus",
+      token,
+      offered_at,
+      offer_expires_at,
+      order_id AS "order_id: OrderId",
+      created_at"#,
+            entry.id,
+            Uuid::new_v4().simple().to_string(),
+            now,
+            now + claim_window,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let ticket_type = ticket_types
+            .get(&entry.ticket_type_id)
+            .map(String::as_str)
+            .unwrap_or_default();
+        let message =
+            email::compose_waitlist_offer(&mut *tx, config, &event, ticket_type, &entry).await?;
+        outbox::enqueue(&mut *tx, EmailKind::WaitlistOffer.as_str(), None, &message).await?;
+
+        count += 1;
+    }
+
+    tx.commit().await?;
+
+    Ok(count)
+}
+
+/// Let offers that have not been claimed in time lapse, and offer freed
+/// tickets to the next people on the waitlist. Returns the number of offers
+/// made.
+pub async fn offer_freed_tickets(
+    pool: &PgPool,
+    config: &EmailConfig,
+    claim_window: Duration,
+) -> Result<usize, EmailError> {
+    sqlx::query!(
+        "UPDATE waitlist SET status = 'lapsed' WHERE status = 'offered' AND offer_expires_at <= NOW()"
+    )
+    .execute(pool)
+    .await?;
+
+    let events =
+        sqlx::query_scalar!("SELECT DISTINCT event_id FROM waitlist WHERE status = 'waiting'")
+            .fetch_all(pool)
+            .await?;
+
+    let mut count = 0;
+    for event_id in events {
+        match offer_event(pool, config, event_id, claim_window).await {
+            Ok(offered) => count += offered,
+            Err(EmailError::Database(e)) => return Err(e.into()),
+            Err(e) => error!(%event_id, "cannot offer tickets to the waitlist: {e}"),
+        }
+    }
+
+    Ok(count)
+}
+
+/// Periodically offer freed tickets to the waitlist, holding them for
+/// `claim_window`. Never returns.
+pub async fn run(pool: PgPool, config: EmailConfig, claim_window: Duration) {
+    let mut interval = tokio::time::interval(INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match offer_freed_tickets(&pool, &config, claim_window).await {
+            Ok(0) => {}
+            Ok(count) => info!("offered tickets to {count} people on the waitlist"),
+            Err(err) => error!("waitlist offers failed: {err}"),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Setup shared by the tests that need a database, given by `DATABASE_URL`.
+//! They are ignored by default and run with
+//!
+//! ```sh
+//! DATABASE_URL=postgres://... cargo test -- --ignored
+//! ```
+//!
+//! The migrations are run on the database, and every test creates the events
+//! it needs.
+
+use std::sync::Arc;
+
+use axum::response::IntoResponse;
+use axum_extra::extract::cookie::{Cookie, Key, PrivateCookieJar};
+use serde_json::json;
+use sqlx::PgPool;
+use tix_api::{
+    email::EmailConfig,
+    oidc::Oidc,
+    routes::AppState,
+    staff::{self, Role},
+    ticket_code::TicketSigner,
+};
+use uuid::Uuid;
+
+pub async fn pool() -> PgPool {
+    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is set");
+    let pool = PgPool::connect(&url).await.unwrap();
+    sqlx::migrate!().run(&pool).await.unwrap();
+
+    pool
+}
+
+pub fn email_config() -> EmailConfig {
+    EmailConfig {
+        from: "Tix <tix@example.com>".parse().unwrap(),
+        reply_to: None,
+        payee: "123-345 69 51".to_owned(),
+        frontend_url: "http://localhost:3000".to_owned(),
+    }
+}
+
+/// The key the login cookie is encrypted with.
+fn cookie_key() -> Key {
+    Key::from(&[7; 64])
+}
+
+/// A `Cookie` header that logs in as an admin.
+#[allow(dead_code)] // not every test needs to log in
+pub async fn admin_cookie(pool: &PgPool) -> String {
+    let email = "admin@example.com";
+    staff::upsert(pool, email, Role::Admin, None).await.unwrap();
+
+    let response = PrivateCookieJar::new(cookie_key())
+        .add(Cookie::new("email", email))
+        .into_response();
+    let set_cookie = response.headers()["set-cookie"].to_str().unwrap();
+
+    set_cookie.split(';').next().unwrap().to_owned()
+}
+
+/// Serve the API on a free port, returning its base URL.
+pub async fn serve(pool: PgPool) -> String {
+    let state = AppState {
+        pool,
+        oidc: Arc::new(Oidc {
+            client_id: openidconnect::ClientId::new("test".to_owned()),
+            client_secret: openidconnect::ClientSecret::new("test".to_owned()),
+        }),
+        cookie_key: cookie_key(),
+        payment_deadline: time::Duration::days(3),
+        ticket_signer: Arc::new(TicketSigner::new(&[7; 32])),
+        email: Arc::new(email_config()),
+        max_tickets_per_order: 10.try_into().unwrap(),
+        swish: None,
+    };
+    let app = tix_api::routes::routes().with_state(state);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    format!("http://{addr}")
+}
+
+/// Create an event on sale with `capacity` tickets, and one ticket type with
+/// `quota` for each element of `quotas`. Returns the ids of the event and
+/// the ticket types.
+pub async fn create_event(pool: &PgPool, capacity: i32, quotas: &[i32]) -> (Uuid, Vec<Uuid>) {
+    let event_id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO events (id, name, venue, starts_at, capacity)
+    VALUES ($1, 'Test', 'Aulan', NOW() + INTERVAL '30 days', $2)",
+    )
+    .bind(event_id)
+    .bind(capacity)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    let mut ticket_types = Vec::with_capacity(quotas.len());
+    for (i, quota) in quotas.iter().enumerate() {
+        let id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO ticket_types (id, event_id, name, price, quota) VALUES ($1, $2, $3, 100, $4)",
+        )
+        .bind(id)
+        .bind(event_id)
+        .bind(format!("Type {i}"))
+        .bind(quota)
+        .execute(pool)
+        .await
+        .unwrap();
+        ticket_types.push(id);
+    }
+
+    (event_id, ticket_types)
+}
+
+/// The body of an order of `count` tickets of `ticket_type_id`.
+pub fn order(email: &str, event_id: Uuid, ticket_type_id: Uuid, count: usize) -> serde_json::Value {
+    json!({
+        "email": email,
+        "name": "Buyer",
+        "phone": "070-123 45 67",
+        "event_id": event_id,
+        "tickets": [{ "ticket_type_id": ticket_type_id, "count": count }],
+    })
+}
@@ -0,0 +1,77 @@
+//! Orders of free tickets have nothing to pay, so they are paid when they are
+//! placed and get their tickets with the next batch. Needs a database, see
+//! [`common`].
+
+mod common;
+
+use reqwest::{header::COOKIE, StatusCode};
+use serde_json::Value;
+
+#[tokio::test]
+#[ignore = "needs a database, run with `cargo test -- --ignored`"]
+async fn free_orders_are_ticketed() {
+    let pool = common::pool().await;
+    let (event_id, ticket_types) = common::create_event(&pool, 10, &[10]).await;
+    let ticket_type_id = ticket_types[0];
+    sqlx::query("UPDATE ticket_types SET price = 0 WHERE id = $1")
+        .bind(ticket_type_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let url = common::serve(pool.clone()).await;
+    let client = reqwest::Client::new();
+    let cookie = common::admin_cookie(&pool).await;
+
+    let response = client
+        .post(format!("{url}/orders"))
+        .json(&common::order(
+            "guest@example.com",
+            event_id,
+            ticket_type_id,
+            2,
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let order: Value = response.json().await.unwrap();
+    assert_eq!(order["amount"], 0);
+    assert_eq!(order["status"], "paid");
+    assert!(order["paid_at"].is_string());
+    let order_id = order["id"].as_str().unwrap();
+
+    let response = client
+        .post(format!("{url}/orders/email"))
+        .header(COOKIE, &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let history: Vec<Value> = client
+        .get(format!("{url}/orders/{order_id}/history"))
+        .header(COOKIE, &cookie)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let kinds = history
+        .iter()
+        .map(|event| {
+            (
+                event["kind"].as_str().unwrap(),
+                event["status"].as_str().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            ("created", "reserved"),
+            ("payment_added", "paid"),
+            ("emailed", "ticketed"),
+        ]
+    );
+}
@@ -0,0 +1,97 @@
+use tix_api::order::OrderStatus::{self, *};
+
+/// Every allowed transition, and nothing else.
+const TRANSITIONS: &[(OrderStatus, OrderStatus)] = &[
+    (Reserved, Paid),
+    (Reserved, Expired),
+    (Reserved, Canceled),
+    // payments can be removed again
+    (Paid, Reserved),
+    (Paid, Ticketed),
+    (Paid, Completed),
+    (Paid, Refunded),
+    (Ticketed, Reserved),
+    (Ticketed, Completed),
+    (Ticketed, Refunded),
+];
+
+#[test]
+fn can_become() {
+    for from in OrderStatus::ALL {
+        for to in OrderStatus::ALL {
+            assert_eq!(
+                from.can_become(to),
+                TRANSITIONS.contains(&(from, to)),
+                "{from} -> {to}"
+            );
+        }
+    }
+}
+
+#[test]
+fn final_statuses() {
+    for status in [Completed, Expired, Canceled, Refunded] {
+        assert!(
+            OrderStatus::ALL.iter().all(|&to| !status.can_become(to)),
+            "{status} is not final"
+        );
+    }
+}
+
+#[test]
+fn sources() {
+    let cases: &[(OrderStatus, &[&str])] = &[
+        (Reserved, &["paid", "ticketed"]),
+        (Paid, &["reserved"]),
+        (Ticketed, &["paid"]),
+        (Completed, &["paid", "ticketed"]),
+        (Expired, &["reserved"]),
+        (Canceled, &["reserved"]),
+        (Refunded, &["paid", "ticketed"]),
+    ];
+
+    for &(next, expected) in cases {
+        assert_eq!(OrderStatus::sources(next), expected, "sources of {next}");
+    }
+}
+
+#[test]
+fn sources_match_can_become() {
+    for next in OrderStatus::ALL {
+        for from in OrderStatus::ALL {
+            assert_eq!(
+                OrderStatus::sources(next).contains(&from.as_str().to_owned()),
+                from.can_become(next),
+                "{from} -> {next}"
+            );
+        }
+    }
+}
+
+#[test]
+fn cancel() {
+    assert_eq!(Reserved.on_cancel(), Canceled);
+    for status in [Paid, Ticketed, Completed] {
+        assert_eq!(status.on_cancel(), Refunded, "canceling {status}");
+    }
+    for status in OrderStatus::ALL {
+        assert_eq!(
+            status.is_canceled(),
+            matches!(status, Expired | Canceled | Refunded),
+            "{status}"
+        );
+    }
+}
+
+#[test]
+fn parse() {
+    for status in OrderStatus::ALL {
+        assert_eq!(status.as_str().parse::<OrderStatus>().unwrap(), status);
+    }
+    for invalid in ["", "Paid", "complete", "paid "] {
+        assert!(
+            invalid.parse::<OrderStatus>().is_err(),
+            "{invalid:?} is parsed"
+        );
+    }
+}
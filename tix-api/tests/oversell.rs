@@ -0,0 +1,81 @@
+//! Many customers ordering the last tickets of an event at once must not get
+//! more tickets than there are. Needs a database, see [`common`].
+
+mod common;
+
+use uuid::Uuid;
+
+const CAPACITY: i64 = 25;
+const QUOTA: i64 = 15;
+const BUYERS: usize = 200;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+#[ignore = "needs a database, run with `cargo test -- --ignored`"]
+async fn concurrent_orders_do_not_oversell() {
+    let pool = common::pool().await;
+
+    // the quotas add up to more than the capacity, so both limits are hit
+    let (event_id, ticket_types) =
+        common::create_event(&pool, CAPACITY as i32, &[QUOTA as i32, QUOTA as i32]).await;
+
+    let url = common::serve(pool.clone()).await;
+    let client = reqwest::Client::new();
+
+    let requests = (0..BUYERS)
+        .map(|i| {
+            let client = client.clone();
+            let url = url.clone();
+            let ticket_type_id = ticket_types[i % ticket_types.len()];
+            tokio::spawn(async move {
+                let response = client
+                    .post(format!("{url}/orders"))
+                    .json(&common::order(
+                        &format!("buyer{i}@example.com"),
+                        event_id,
+                        ticket_type_id,
+                        1 + i % 3,
+                    ))
+                    .send()
+                    .await
+                    .unwrap();
+                response.status()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut created = 0;
+    for request in requests {
+        let status = request.await.unwrap();
+        assert!(
+            status == reqwest::StatusCode::CREATED || status == reqwest::StatusCode::BAD_REQUEST,
+            "unexpected status {status}"
+        );
+        if status == reqwest::StatusCode::CREATED {
+            created += 1;
+        }
+    }
+
+    let sold: Vec<(Uuid, i64)> = sqlx::query_as(
+        "SELECT t.ticket_type_id, COUNT(*)
+    FROM tickets t
+    JOIN ticket_types tt ON tt.id = t.ticket_type_id
+    WHERE tt.event_id = $1
+    GROUP BY t.ticket_type_id",
+    )
+    .bind(event_id)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    let total: i64 = sold.iter().map(|(_, count)| count).sum();
+
+    assert!(created > 0);
+    assert!(total <= CAPACITY, "sold {total} tickets of {CAPACITY}");
+    // there are far more buyers than tickets, so the event must sell out
+    assert_eq!(total, CAPACITY, "sold {total} tickets of {CAPACITY}");
+    for (ticket_type_id, count) in sold {
+        assert!(
+            count <= QUOTA,
+            "sold {count} tickets of type {ticket_type_id}, with a quota of {QUOTA}"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use rust_decimal::Decimal;
+use serde_json::Value;
+use time::OffsetDateTime;
+use tix_api::{
+    order::OrderId,
+    payment,
+    reconcile::{self, Outcome},
+    swish::{Part, Transaction},
+};
+
+fn ids(ids: &[&str]) -> Vec<OrderId> {
+    ids.iter().map(|id| OrderId::from(id.to_string())).collect()
+}
+
+#[test]
+fn normalize() {
+    let cases = [
+        ("CDEFHJKM", Some("CDEFHJKM")),
+        ("cdefhjkm", Some("CDEFHJKM")),
+        (" CDEF HJKM ", Some("CDEFHJKM")),
+        ("CDEF-HJKM", Some("CDEFHJKM")),
+        ("CD\tEF\nHJKM", Some("CDEFHJKM")),
+        // letters and digits left out of order ids
+        ("OQ0DCDEF", Some("DDDDCDEF")),
+        ("ABGSZUCD", Some("48652VCD")),
+        ("7TTTCDEF", Some("TTTTCDEF")),
+        ("dk4m-7qx2", Some("DK4MTDX2")),
+        // too short or too long
+        ("", None),
+        ("CDEFHJK", None),
+        ("CDEFHJKMN", None),
+        ("CDEF-HJK", None),
+        // characters that are not read as anything else
+        ("CDEFHJK1", None),
+        ("CDEFHJKI", None),
+        ("CDEFHJKL", None),
+        ("CDEFHJK_", None),
+        ("CDEFHJKÅ", None),
+    ];
+
+    for (input, expected) in cases {
+        assert_eq!(
+            OrderId::normalize(input),
+            expected.map(|id| OrderId::from(id.to_owned())),
+            "normalizing {input:?}"
+        );
+    }
+}
+
+#[test]
+fn candidates() {
+    let cases: &[(&str, &[&str])] = &[
+        ("CDEFHJKM", &["CDEFHJKM"]),
+        ("Order cdefhjkm, tack!", &["CDEFHJKM"]),
+        // split in two, or with a separator
+        ("CDEF HJKM", &["CDEFHJKM"]),
+        ("DK4M-7QX2", &["DK4MTDX2"]),
+        ("Biljetter: CDE FHJ KM", &["CDEFHJKM"]),
+        // the same id twice is only considered once
+        ("CDEFHJKM CDEFHJKM", &["CDEFHJKM"]),
+        // nothing that could be an id
+        ("", &[]),
+        ("Biljetter till Vision", &[]),
+        ("0701234567", &[]),
+        ("CDEFHJK", &[]),
+    ];
+
+    for (message, expected) in cases {
+        assert_eq!(
+            reconcile::candidates(message),
+            ids(expected),
+            "candidates in {message:?}"
+        );
+    }
+}
+
+#[test]
+fn ambiguous_candidates_keep_their_order() {
+    // two ids, most likely the first
+    assert_eq!(
+        reconcile::candidates("CDEFHJKM eller PRTVWXY2"),
+        ids(&["CDEFHJKM", "PRTVWXY2"])
+    );
+
+    // ordinary words can read as an id, so they are candidates too and it is
+    // up to matching to skip those that are not orders
+    assert_eq!(
+        reconcile::candidates("Tacksamt CDEFHJKM"),
+        ids(&["T4CK54MT", "CDEFHJKM"])
+    );
+
+    // words are joined with their neighbors until they are long enough, so
+    // one message can give overlapping candidates
+    assert_eq!(
+        reconcile::candidates("CDEF HJKM PRTV"),
+        ids(&["CDEFHJKM", "HJKMPRTV"])
+    );
+}
+
+fn transaction(amount: i64, message: &str) -> Transaction {
+    Transaction {
+        time: OffsetDateTime::now_utc(),
+        amount: Decimal::from(amount),
+        message: message.to_owned(),
+        payor: Part {
+            phone: "46701234567".to_owned(),
+            name: "Åsa Öberg".to_owned(),
+        },
+        payee: Part {
+            phone: "1233456951".to_owned(),
+            name: "Elevkåren".to_owned(),
+        },
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a database, run with `cargo test -- --ignored`"]
+async fn outgoing_payments_are_not_matched() {
+    let pool = common::pool().await;
+    let (event_id, ticket_types) = common::create_event(&pool, 10, &[10]).await;
+    let url = common::serve(pool.clone()).await;
+    let order: Value = reqwest::Client::new()
+        .post(format!("{url}/orders"))
+        .json(&common::order(
+            "buyer@example.com",
+            event_id,
+            ticket_types[0],
+            1,
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let order_id = order["id"].as_str().unwrap();
+
+    // e.g. a refund of the order, or a debit entry of a bank statement
+    let mut tx = pool.begin().await.unwrap();
+    let import = payment::import(
+        &mut tx,
+        None,
+        "admin@example.com",
+        vec![transaction(-100, order_id), transaction(100, order_id)],
+    )
+    .await
+    .unwrap();
+    let report = reconcile::reconcile(&mut *tx, import.payments)
+        .await
+        .unwrap();
+    tx.rollback().await.unwrap();
+
+    assert_eq!(report.rows[0].outcome, Outcome::Outgoing);
+    assert_eq!(
+        report.rows[1].outcome,
+        Outcome::Matched {
+            order_id: OrderId::from(order_id.to_owned())
+        }
+    );
+    assert_eq!(report.summary.outgoing, 1);
+    assert_eq!(report.summary.matched, 1);
+}
@@ -0,0 +1,282 @@
+mod common;
+
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use time::macros::datetime;
+use tix_api::{
+    order::OrderId,
+    swish::commerce::{self, payer_alias, Client, CommerceConfig, CommerceError, RequestStatus},
+};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{body_json, header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const ID: &str = "11A86BE70EA346E4B1C39C874173F088";
+
+fn client(server: &MockServer) -> Client {
+    Client::new(
+        reqwest::Client::new(),
+        CommerceConfig {
+            api_url: server.uri(),
+            payee_alias: "1233456951".to_owned(),
+            callback_url: "https://api.example.com/payments/swish/callback".to_owned(),
+        },
+    )
+}
+
+#[tokio::test]
+async fn create() {
+    let server = MockServer::start().await;
+    Mock::given(method("PUT"))
+        .and(path(format!("/api/v2/paymentrequests/{ID}")))
+        .and(body_json(json!({
+            "payeePaymentReference": "DK4M7QX2",
+            "callbackUrl": "https://api.example.com/payments/swish/callback",
+            "payeeAlias": "1233456951",
+            "payerAlias": "46701234567",
+            "amount": "230.00",
+            "currency": "SEK",
+            "message": "DK4M7QX2",
+        })))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let token = client(&server)
+        .create(
+            ID,
+            &OrderId::from("DK4M7QX2".to_owned()),
+            230,
+            Some("46701234567"),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(token, None);
+}
+
+#[tokio::test]
+async fn create_without_payer() {
+    let server = MockServer::start().await;
+    Mock::given(method("PUT"))
+        .and(path(format!("/api/v2/paymentrequests/{ID}")))
+        .respond_with(
+            ResponseTemplate::new(201)
+                .insert_header("PaymentRequestToken", "c28a4061470f4af48973bd2a4642b4fa"),
+        )
+        .mount(&server)
+        .await;
+
+    let token = client(&server)
+        .create(ID, &OrderId::from("DK4M7QX2".to_owned()), 230, None)
+        .await
+        .unwrap();
+
+    assert_eq!(token.as_deref(), Some("c28a4061470f4af48973bd2a4642b4fa"));
+}
+
+#[tokio::test]
+async fn create_rejected() {
+    let server = MockServer::start().await;
+    Mock::given(method("PUT"))
+        .respond_with(ResponseTemplate::new(422).set_body_json(json!([
+            { "errorCode": "BE18", "errorMessage": "Payer alias is invalid" },
+        ])))
+        .mount(&server)
+        .await;
+
+    let err = client(&server)
+        .create(ID, &OrderId::from("DK4M7QX2".to_owned()), 230, Some("4670"))
+        .await
+        .unwrap_err();
+
+    match err {
+        CommerceError::Rejected(errors) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].error_code, "BE18");
+        }
+        err => panic!("unexpected error: {err}"),
+    }
+}
+
+#[tokio::test]
+async fn get_paid() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(format!("/api/v1/paymentrequests/{ID}")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": ID,
+            "payeePaymentReference": "DK4M7QX2",
+            "paymentReference": "1E2FC19E5E5E4E18916609B7F8911C12",
+            "callbackUrl": "https://api.example.com/payments/swish/callback",
+            "payerAlias": "46701234567",
+            "payeeAlias": "1233456951",
+            "amount": 230.00,
+            "currency": "SEK",
+            "message": "DK4M7QX2",
+            "status": "PAID",
+            "dateCreated": "2024-01-05T13:02:58.184Z",
+            "datePaid": "2024-01-05T13:03:21.364Z",
+            "errorCode": null,
+            "errorMessage": null,
+        })))
+        .mount(&server)
+        .await;
+
+    let state = client(&server).get(ID).await.unwrap();
+
+    assert_eq!(state.status, RequestStatus::Paid);
+    assert_eq!(state.amount, Decimal::new(230, 0));
+    assert_eq!(state.payer_alias.as_deref(), Some("46701234567"));
+    assert_eq!(
+        state.date_paid,
+        Some(datetime!(2024-01-05 13:03:21.364 UTC))
+    );
+}
+
+#[tokio::test]
+async fn get_declined() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": ID,
+            "amount": 230,
+            "status": "DECLINED",
+            "errorCode": "RF07",
+            "errorMessage": "Transaction declined",
+        })))
+        .mount(&server)
+        .await;
+
+    let state = client(&server).get(ID).await.unwrap();
+
+    assert_eq!(state.status, RequestStatus::Declined);
+    assert_eq!(state.date_paid, None);
+    assert_eq!(state.error_code.as_deref(), Some("RF07"));
+}
+
+#[tokio::test]
+async fn get_unknown() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+
+    let err = client(&server).get(ID).await.unwrap_err();
+
+    assert!(matches!(err, CommerceError::NotFound));
+}
+
+fn cancelled(id: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "id": id,
+        "amount": 230,
+        "status": "CANCELLED",
+    }))
+}
+
+#[tokio::test]
+async fn cancel() {
+    let server = MockServer::start().await;
+    Mock::given(method("PATCH"))
+        .and(path(format!("/api/v1/paymentrequests/{ID}")))
+        .and(header("content-type", "application/json-patch+json"))
+        .and(body_json(json!([
+            { "op": "replace", "path": "/status", "value": "cancelled" },
+        ])))
+        .respond_with(cancelled(ID))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let state = client(&server).cancel(ID).await.unwrap();
+
+    assert_eq!(state.status, RequestStatus::Cancelled);
+}
+
+#[tokio::test]
+#[ignore = "needs a database, run with `cargo test -- --ignored`"]
+async fn requests_of_closed_orders_are_canceled() {
+    let pool = common::pool().await;
+    let (event_id, ticket_types) = common::create_event(&pool, 10, &[10]).await;
+    let url = common::serve(pool.clone()).await;
+    let order: Value = reqwest::Client::new()
+        .post(format!("{url}/orders"))
+        .json(&common::order(
+            "buyer@example.com",
+            event_id,
+            ticket_types[0],
+            1,
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let order_id = order["id"].as_str().unwrap();
+
+    let id = Uuid::new_v4().simple().to_string().to_uppercase();
+    sqlx::query(
+        "INSERT INTO swish_payment_requests (id, order_id, amount, token) VALUES ($1, $2, 100, 'c28a4061470f4af48973bd2a4642b4fa')",
+    )
+    .bind(&id)
+    .bind(order_id)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    // the open request is offered for paying the order
+    let request: Value = reqwest::get(format!(
+        "{url}/orders/{order_id}/swish?email=buyer@example.com"
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(
+        request["request_url"],
+        "swish://paymentrequest?token=c28a4061470f4af48973bd2a4642b4fa"
+    );
+
+    let server = MockServer::start().await;
+    Mock::given(method("PATCH"))
+        .and(path(format!("/api/v1/paymentrequests/{id}")))
+        .respond_with(cancelled(&id))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // expired without going through the API
+    sqlx::query("UPDATE orders SET status = 'expired', canceled_at = NOW() WHERE id = $1")
+        .bind(order_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    commerce::poll(&pool, &client(&server)).await.unwrap();
+
+    let status: String =
+        sqlx::query_scalar("SELECT status::text FROM swish_payment_requests WHERE id = $1")
+            .bind(&id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(status, "cancelled");
+}
+
+#[test]
+fn payer_aliases() {
+    assert_eq!(payer_alias("070-123 45 67").as_deref(), Some("46701234567"));
+    assert_eq!(
+        payer_alias("+46 70 123 45 67").as_deref(),
+        Some("46701234567")
+    );
+    assert_eq!(payer_alias("0046701234567").as_deref(), Some("46701234567"));
+    assert_eq!(payer_alias("08-123 456 78"), None);
+    assert_eq!(payer_alias("+47 412 34 567"), None);
+}
@@ -0,0 +1,176 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use time::macros::datetime;
+use tix_api::{
+    error::ResponseError,
+    swish::{self, Transaction},
+};
+
+fn fixture(name: &str) -> Vec<u8> {
+    std::fs::read(format!(
+        "{}/tests/fixtures/swish/{name}",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap()
+}
+
+fn parse(name: &str) -> (&'static str, Vec<Transaction>) {
+    let input = fixture(name);
+    let format = swish::detect(&input).expect("format is detected");
+
+    (format.name(), swish::parse_transactions(&input).unwrap())
+}
+
+#[test]
+fn swish_csv() {
+    let (format, transactions) = parse("swish.csv");
+
+    assert_eq!(format, "swish_csv");
+    assert_eq!(transactions.len(), 2);
+
+    let t = &transactions[0];
+    assert_eq!(t.time, datetime!(2024-01-05 14:03:21 +01:00));
+    assert_eq!(t.amount, Decimal::new(15000, 2));
+    assert_eq!(t.message, "DK4M-7QX2");
+    assert_eq!(t.payor.phone, "46701234567");
+    assert_eq!(t.payor.name, "Åsa Öberg");
+    assert_eq!(t.payee.phone, "1233456951");
+    assert_eq!(t.payee.name, "Elevkåren");
+
+    assert_eq!(transactions[1].amount, Decimal::new(-5000, 2));
+    assert_eq!(transactions[1].message, "Återbetalning");
+}
+
+#[test]
+fn bank_csv() {
+    let (format, transactions) = parse("bank.csv");
+
+    assert_eq!(format, "bank_csv");
+    assert_eq!(transactions.len(), 2);
+
+    let t = &transactions[0];
+    assert_eq!(t.time, datetime!(2024-01-05 14:03 +01:00));
+    assert_eq!(t.amount, Decimal::new(123450, 2));
+    assert_eq!(t.message, "order DK4M 7QX2");
+    assert_eq!(t.payor.name, "Åsa Öberg");
+    assert_eq!(t.payee.name, "Elevkåren");
+
+    assert_eq!(transactions[1].time, datetime!(2024-01-06 09:15:30 +01:00));
+    assert_eq!(transactions[1].amount, Decimal::new(-5000, 2));
+    assert_eq!(transactions[1].message, "tack; hej");
+}
+
+#[test]
+fn camt053() {
+    let (format, transactions) = parse("camt053.xml");
+
+    assert_eq!(format, "camt053");
+    assert_eq!(transactions.len(), 4);
+
+    let t = &transactions[0];
+    assert_eq!(t.time, datetime!(2024-01-05 14:03:21 +01:00));
+    assert_eq!(t.amount, Decimal::new(15000, 2));
+    assert_eq!(t.message, "DK4M-7QX2");
+    assert_eq!(t.payor.phone, "46701234567");
+    assert_eq!(t.payor.name, "Åsa Öberg");
+    assert_eq!(t.payee.phone, "1233456951");
+    assert_eq!(t.payee.name, "Elevkåren");
+
+    // a batched entry is split by its transaction details
+    assert_eq!(transactions[1].time, datetime!(2024-01-06 00:00 +01:00));
+    assert_eq!(transactions[1].amount, Decimal::new(10000, 2));
+    assert_eq!(transactions[1].payor.name, "Per Ek");
+    assert_eq!(transactions[2].amount, Decimal::new(20000, 2));
+    assert_eq!(transactions[2].message, "Biljetter");
+
+    assert_eq!(transactions[3].time, datetime!(2024-01-07 10:00 +01:00));
+    assert_eq!(transactions[3].amount, Decimal::new(-2500, 2));
+    assert_eq!(transactions[3].message, "Avgift");
+}
+
+#[test]
+fn unrecognized_format() {
+    let err = swish::parse_transactions(b"hello world").unwrap_err();
+
+    assert_eq!(err.message, "unrecognized report format");
+}
+
+#[test]
+fn invalid_cell() {
+    let input = String::from_utf8(fixture("bank.csv"))
+        .unwrap()
+        .replace("1 234,50", "1 234,5O");
+    let err = swish::parse_transactions(input.as_bytes()).unwrap_err();
+
+    assert_eq!(err.row, Some(2));
+    assert_eq!(err.column.as_deref(), Some("Belopp"));
+}
+
+#[test]
+fn invalid_xml_element() {
+    let input = String::from_utf8(fixture("camt053.xml"))
+        .unwrap()
+        .replace("<Dt>2024-01-06</Dt>", "<Dt>2024-13-06</Dt>");
+    let err = swish::parse_transactions(input.as_bytes()).unwrap_err();
+
+    assert_eq!(err.row, Some(55));
+    assert_eq!(err.column.as_deref(), Some("Dt"));
+}
+
+async fn error_body(err: ResponseError) -> (StatusCode, Value) {
+    let response = err.into_response();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn report_error_response() {
+    let input = String::from_utf8(fixture("bank.csv"))
+        .unwrap()
+        .replace("1 234,50", "1 234,5O");
+    let err = swish::parse_transactions(input.as_bytes()).unwrap_err();
+    let (status, body) = error_body(err.into()).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_swish_report");
+    assert_eq!(body["row"], 2);
+    assert_eq!(body["column"], "Belopp");
+    assert!(body["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("row 2, column Belopp: "));
+}
+
+#[tokio::test]
+async fn invalid_upload_response() {
+    let (status, body) = error_body(swish::invalid_upload("no file uploaded")).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body,
+        json!({
+            "code": "invalid_upload",
+            "message": "no file uploaded",
+            "row": null,
+            "column": null,
+        })
+    );
+}
+
+#[test]
+fn swish_csv_with_bom() {
+    // e.g. after being opened and saved in a spreadsheet
+    let input = fixture("swish.csv");
+    let (utf8, _, _) = encoding_rs::WINDOWS_1252.decode(&input);
+    let input = [b"\xef\xbb\xbf", utf8.as_bytes()].concat();
+
+    assert_eq!(swish::detect(&input).unwrap().name(), "swish_csv");
+    let transactions = swish::parse_transactions(&input).unwrap();
+    assert_eq!(transactions.len(), 2);
+    assert_eq!(transactions[0].payor.name, "Åsa Öberg");
+}
@@ -0,0 +1,77 @@
+use time::macros::datetime;
+use tix_api::{
+    locale::Locale,
+    order::{Order, OrderStatus},
+    swish::request::PaymentRequest,
+};
+use uuid::Uuid;
+
+fn order() -> Order {
+    Order {
+        id: "CDEFHJKM".to_owned().into(),
+        email: "asa@example.com".to_owned(),
+        name: "Åsa Öberg".to_owned(),
+        phone: "070-123 45 67".to_owned(),
+        amount: 345,
+        created_at: datetime!(2024-01-05 14:03:21 UTC),
+        checked_out_at: None,
+        paid_at: None,
+        canceled_at: None,
+        emailed_at: None,
+        event_id: Uuid::nil(),
+        reminded_at: None,
+        locale: Locale::Sv,
+        status: OrderStatus::Reserved,
+    }
+}
+
+#[test]
+fn payment_request() {
+    let request = PaymentRequest::for_order("123-345 69 51", &order());
+
+    assert_eq!(request.payee, "1233456951");
+    assert_eq!(request.amount, 345);
+    assert_eq!(request.message, "CDEFHJKM");
+    assert_eq!(request.qr_data, "C1233456951;345;CDEFHJKM;0");
+    assert_eq!(
+        request.url,
+        "swish://payment?data=\
+         %7B%22amount%22%3A%7B%22value%22%3A345%7D%2C\
+         %22message%22%3A%7B%22editable%22%3Afalse%2C%22value%22%3A%22CDEFHJKM%22%7D%2C\
+         %22payee%22%3A%7B%22value%22%3A%221233456951%22%7D%2C\
+         %22version%22%3A1%7D"
+    );
+}
+
+#[test]
+fn payment_request_url_data() {
+    let request = PaymentRequest::for_order("123-345 69 51", &order());
+    let query = request.url.strip_prefix("swish://payment?").unwrap();
+    let [(key, data)]: [(String, String); 1] = serde_urlencoded::from_str::<Vec<_>>(query)
+        .unwrap()
+        .try_into()
+        .unwrap();
+
+    assert_eq!(key, "data");
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&data).unwrap(),
+        serde_json::json!({
+            "version": 1,
+            "payee": { "value": "1233456951" },
+            "amount": { "value": 345 },
+            "message": { "value": "CDEFHJKM", "editable": false },
+        })
+    );
+}
+
+#[test]
+fn payment_request_with_token() {
+    let request = PaymentRequest::for_order("123-345 69 51", &order());
+    assert_eq!(request.request_url, None);
+
+    let request = request.with_token("c28a4061470f4af48973bd2a4642b4fa");
+    assert_eq!(
+        request.request_url.as_deref(),
+        Some("swish://paymentrequest?token=c28a4061470f4af48973bd2a4642b4fa")
+    );
+}
@@ -0,0 +1,106 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use tix_api::ticket_code::{self, TicketClaims, TicketSigner};
+use uuid::{uuid, Uuid};
+
+fn claims() -> TicketClaims {
+    TicketClaims {
+        ticket_id: uuid!("6f1c2a8e-3b1d-4c57-9a0e-2f4d8b7c1e90"),
+        event_id: uuid!("0d9e4b2a-7c6f-4e1a-8b3d-5a2c9f0e7d14"),
+        order_id: "DK4M-7QX2".to_owned().into(),
+    }
+}
+
+fn signer() -> TicketSigner {
+    TicketSigner::new(&[7; 32])
+}
+
+#[test]
+fn round_trip() {
+    let signer = signer();
+    let code = signer.sign(&claims());
+
+    let verified = ticket_code::verify(&signer.verifying_key(), &code).unwrap();
+    assert_eq!(verified, claims());
+}
+
+#[test]
+fn surrounding_whitespace_is_ignored() {
+    let signer = signer();
+    let code = format!(" {}\n", signer.sign(&claims()));
+
+    assert!(ticket_code::verify(&signer.verifying_key(), &code).is_ok());
+}
+
+#[test]
+fn tampered_payload_is_rejected() {
+    let signer = signer();
+    let code = signer.sign(&claims());
+    let (_, signature) = code.split_once('.').unwrap();
+
+    let mut payload = Vec::new();
+    payload.extend_from_slice(Uuid::new_v4().as_bytes());
+    payload.extend_from_slice(claims().event_id.as_bytes());
+    payload.extend_from_slice(b"DK4M-7QX2");
+    let tampered = format!("{}.{signature}", URL_SAFE_NO_PAD.encode(payload));
+
+    assert!(ticket_code::verify(&signer.verifying_key(), &tampered).is_err());
+}
+
+#[test]
+fn tampered_signature_is_rejected() {
+    let signer = signer();
+    let code = signer.sign(&claims());
+    let (payload, signature) = code.split_once('.').unwrap();
+
+    let mut signature = URL_SAFE_NO_PAD.decode(signature).unwrap();
+    signature[0] ^= 1;
+    let tampered = format!("{payload}.{}", URL_SAFE_NO_PAD.encode(signature));
+
+    assert!(ticket_code::verify(&signer.verifying_key(), &tampered).is_err());
+}
+
+#[test]
+fn wrong_key_is_rejected() {
+    let code = signer().sign(&claims());
+    let other = TicketSigner::new(&[8; 32]);
+
+    assert!(ticket_code::verify(&other.verifying_key(), &code).is_err());
+}
+
+#[test]
+fn malformed_codes_are_rejected() {
+    let signer = signer();
+    let key = signer.verifying_key();
+    let code = signer.sign(&claims());
+    let (payload, signature) = code.split_once('.').unwrap();
+
+    for malformed in [
+        "",
+        ".",
+        payload,
+        &format!("{payload}."),
+        &format!(".{signature}"),
+        &format!("{payload}!.{signature}"),
+        &format!("{payload}.{signature}*"),
+        &format!("{payload}==.{signature}"),
+        &format!("{payload}.{}", &signature[..signature.len() - 4]),
+    ] {
+        assert!(
+            ticket_code::verify(&key, malformed).is_err(),
+            "{malformed:?} is accepted"
+        );
+    }
+}
+
+#[test]
+fn short_payload_is_rejected() {
+    let signer = signer();
+    // validly signed, but too short to hold the ticket, event and order ids
+    let claims = TicketClaims {
+        order_id: String::new().into(),
+        ..claims()
+    };
+    let code = signer.sign(&claims);
+
+    assert!(ticket_code::verify(&signer.verifying_key(), &code).is_err());
+}
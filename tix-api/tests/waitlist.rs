@@ -0,0 +1,252 @@
+//! Tickets freed from a sold out event go to the waitlist, in the order
+//! people signed up, before anyone else can order them. Needs a database, see
+//! [`common`].
+
+mod common;
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use time::Duration;
+use tix_api::waitlist::{self, WaitlistStatus};
+use uuid::Uuid;
+
+async fn sign_up(
+    client: &reqwest::Client,
+    url: &str,
+    event_id: Uuid,
+    ticket_type_id: Uuid,
+    email: &str,
+    count: usize,
+) -> reqwest::Response {
+    client
+        .post(format!("{url}/waitlist"))
+        .json(&json!({
+            "email": email,
+            "name": "Waiting",
+            "phone": "070-123 45 67",
+            "event_id": event_id,
+            "ticket_type_id": ticket_type_id,
+            "count": count,
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn join(
+    client: &reqwest::Client,
+    url: &str,
+    event_id: Uuid,
+    ticket_type_id: Uuid,
+    email: &str,
+    count: usize,
+) -> Uuid {
+    let response = sign_up(client, url, event_id, ticket_type_id, email, count).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let entry: Value = response.json().await.unwrap();
+    assert!(entry.get("token").is_none(), "the claim token is not shown");
+
+    entry["id"].as_str().unwrap().parse().unwrap()
+}
+
+async fn order(
+    client: &reqwest::Client,
+    url: &str,
+    event_id: Uuid,
+    ticket_type_id: Uuid,
+    count: usize,
+) -> reqwest::Response {
+    client
+        .post(format!("{url}/orders"))
+        .json(&common::order(
+            "buyer@example.com",
+            event_id,
+            ticket_type_id,
+            count,
+        ))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn cancel(client: &reqwest::Client, url: &str, order: &Value) {
+    let order_id = order["id"].as_str().unwrap();
+    let email = order["email"].as_str().unwrap();
+    let response = client
+        .delete(format!("{url}/orders/{order_id}"))
+        .query(&[("email", email)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+async fn remaining(client: &reqwest::Client, url: &str, event_id: Uuid) -> u64 {
+    let remaining: Value = client
+        .get(format!("{url}/events/{event_id}/remaining"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    remaining["total"].as_u64().unwrap()
+}
+
+async fn status(pool: &PgPool, id: Uuid) -> WaitlistStatus {
+    waitlist::get(pool, id).await.unwrap().unwrap().status
+}
+
+async fn offer(pool: &PgPool) {
+    waitlist::offer_freed_tickets(pool, &common::email_config(), Duration::hours(1))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs a database, run with `cargo test -- --ignored`"]
+async fn freed_tickets_are_offered_and_claimed() {
+    let pool = common::pool().await;
+    let (event_id, ticket_types) = common::create_event(&pool, 2, &[2]).await;
+    let ticket_type_id = ticket_types[0];
+    let url = common::serve(pool.clone()).await;
+    let client = reqwest::Client::new();
+
+    // sell out
+    let response = order(&client, &url, event_id, ticket_type_id, 2).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let sold: Value = response.json().await.unwrap();
+    assert_eq!(remaining(&client, &url, event_id).await, 0);
+
+    // the database is shared between runs
+    let first_email = format!("first-{event_id}@example.com");
+    let first = join(&client, &url, event_id, ticket_type_id, &first_email, 2).await;
+    let second_email = format!("second-{event_id}@example.com");
+    let second = join(&client, &url, event_id, ticket_type_id, &second_email, 1).await;
+
+    // the freed tickets are held for the waitlist before they are offered
+    cancel(&client, &url, &sold).await;
+    assert_eq!(remaining(&client, &url, event_id).await, 0);
+    let response = order(&client, &url, event_id, ticket_type_id, 1).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // both tickets go to the first to sign up, the second has to wait
+    offer(&pool).await;
+    assert_eq!(status(&pool, first).await, WaitlistStatus::Offered);
+    assert_eq!(status(&pool, second).await, WaitlistStatus::Waiting);
+    assert_eq!(remaining(&client, &url, event_id).await, 0);
+
+    let offers: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM outbox WHERE kind = 'waitlist_offer' AND $1 = ANY(envelope_to)",
+    )
+    .bind(&first_email)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(offers, 1);
+
+    let token = waitlist::get(&pool, first)
+        .await
+        .unwrap()
+        .unwrap()
+        .token
+        .unwrap();
+    let entry: Value = client
+        .get(format!("{url}/waitlist/{first}"))
+        .query(&[("token", &token)])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(entry["status"], "offered");
+
+    let claim = |token: String| {
+        client
+            .post(format!("{url}/waitlist/{first}/claim"))
+            .json(&json!({ "token": token }))
+            .send()
+    };
+
+    let response = claim("wrong".to_owned()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = claim(token.clone()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let claimed: Value = response.json().await.unwrap();
+    assert_eq!(status(&pool, first).await, WaitlistStatus::Claimed);
+    let tickets: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM tickets WHERE order_id = $1")
+        .bind(claimed["id"].as_str().unwrap())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(tickets, 2);
+    assert_eq!(remaining(&client, &url, event_id).await, 0);
+
+    let response = claim(token).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    // freeing the claimed tickets holds one for the second to sign up, and
+    // the other can be ordered by anyone
+    cancel(&client, &url, &claimed).await;
+    assert_eq!(remaining(&client, &url, event_id).await, 1);
+    offer(&pool).await;
+    assert_eq!(status(&pool, second).await, WaitlistStatus::Offered);
+    assert_eq!(remaining(&client, &url, event_id).await, 1);
+
+    // an offer that is not claimed in time lapses, and its ticket is freed
+    sqlx::query("UPDATE waitlist SET offer_expires_at = NOW() - INTERVAL '1 minute' WHERE id = $1")
+        .bind(second)
+        .execute(&pool)
+        .await
+        .unwrap();
+    offer(&pool).await;
+    assert_eq!(status(&pool, second).await, WaitlistStatus::Lapsed);
+    assert_eq!(remaining(&client, &url, event_id).await, 2);
+}
+
+#[tokio::test]
+#[ignore = "needs a database, run with `cargo test -- --ignored`"]
+async fn cannot_join_while_tickets_remain() {
+    let pool = common::pool().await;
+    let (event_id, ticket_types) = common::create_event(&pool, 2, &[2]).await;
+    let ticket_type_id = ticket_types[0];
+    let url = common::serve(pool.clone()).await;
+    let client = reqwest::Client::new();
+    let email = format!("waiting-{event_id}@example.com");
+
+    // the tickets can be ordered, and joining would hold them back
+    for count in [1, 2] {
+        let response = sign_up(&client, &url, event_id, ticket_type_id, &email, count).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            response.text().await.unwrap(),
+            "there are tickets left to order"
+        );
+    }
+    assert_eq!(remaining(&client, &url, event_id).await, 2);
+
+    // more than are left
+    let id = join(&client, &url, event_id, ticket_type_id, &email, 3).await;
+    assert_eq!(remaining(&client, &url, event_id).await, 2);
+
+    // signing up again changes the count instead of adding another entry
+    let response = sign_up(&client, &url, event_id, ticket_type_id, &email, 4).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let entry: Value = response.json().await.unwrap();
+    assert_eq!(entry["id"], id.to_string());
+    assert_eq!(entry["count"], 4);
+
+    // but not once tickets have been offered
+    sqlx::query("UPDATE waitlist SET status = 'offered' WHERE id = $1")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let response = sign_up(&client, &url, event_id, ticket_type_id, &email, 4).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(status(&pool, id).await, WaitlistStatus::Offered);
+}
@@ -26,7 +26,7 @@ export default function Checkout() {
   //   return null;
   // }
 
-  if (order?.completed_at) {
+  if (order?.checked_out_at) {
     return (
       <div className="text-center">
         <CheckCircle className="mx-auto mb-4 size-20 rounded-xl bg-green-100 p-4 text-green-500" />
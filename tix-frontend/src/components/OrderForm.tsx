@@ -1,10 +1,5 @@
-import {
-  CreateOrder,
-  Order,
-  createOrder,
-  getTicketsRemaining,
-} from "@/lib/api";
-import { useQuery } from "@tanstack/react-query";
+import { Order, TicketType, createOrder } from "@/lib/api";
+import { useCurrentEvent, useTicketsRemaining } from "@/lib/hooks";
 import { useState } from "react";
 
 function Field({
@@ -32,51 +27,54 @@ function Field({
 }
 
 function QuantityField({
+  ticketType,
   value,
   onChange,
-  price,
+  remaining,
 }: {
+  ticketType: TicketType;
   value: number;
   onChange: (value: number) => void;
-  price: number;
+  remaining?: number;
 }) {
   return (
-    <div>
-      <label className="block">
-        <p className="mb-1 text-sm font-medium">Antal biljetter</p>
-        <p className="flex items-center gap-2">
-          <input
-            type="number"
-            name="count"
-            value={value}
-            onChange={(e) => onChange(parseInt(e.target.value))}
-            className="w-full rounded-lg border border-gray-200 p-2 text-sm"
-            min={1}
-            max={10}
-            required
-          />
-          <span className="grow whitespace-nowrap">à {price}&nbsp;kr</span>
-        </p>
-      </label>
-      <p className="mt-1 text-xs text-gray-500">
-        I biljettpriset ingår garderob.
+    <label className="block">
+      <p className="mb-1 text-sm font-medium">{ticketType.name}</p>
+      <p className="flex items-center gap-2">
+        <input
+          type="number"
+          name={`count-${ticketType.id}`}
+          value={value}
+          onChange={(e) => onChange(parseInt(e.target.value) || 0)}
+          className="w-full rounded-lg border border-gray-200 p-2 text-sm"
+          min={0}
+          max={Math.min(10, remaining ?? 10)}
+          disabled={remaining === 0}
+        />
+        <span className="grow whitespace-nowrap">
+          à {ticketType.price}&nbsp;kr
+        </span>
       </p>
-    </div>
+    </label>
   );
 }
 
 export default function OrderForm(props: { onCreate: (order: Order) => void }) {
-  const { data: ticketsRemaining } = useQuery({
-    queryKey: ["ticketsRemaining"],
-    queryFn: getTicketsRemaining,
-  });
-  const [count, setCount] = useState(1);
-  const price = 115;
-  const total = price * count;
+  const { data: event } = useCurrentEvent();
+  const { data: ticketsRemaining } = useTicketsRemaining(event?.id);
+  const remaining = ticketsRemaining ?? event?.tickets_remaining;
+  const ticketTypes = event?.ticket_types ?? [];
+  const [counts, setCounts] = useState<Record<string, number>>({});
+  const count = Object.values(counts).reduce((sum, n) => sum + n, 0);
+  const total = ticketTypes.reduce(
+    (sum, t) => sum + t.price * (counts[t.id] ?? 0),
+    0,
+  );
   const [creating, setCreating] = useState(false);
   const [error, setError] = useState<string | null>(null);
   const onSubmit = (e: React.FormEvent<HTMLFormElement>) => {
     e.preventDefault();
+    if (!event) return;
     setError(null);
     setCreating(true);
     const formData = new FormData(e.currentTarget);
@@ -84,7 +82,10 @@ export default function OrderForm(props: { onCreate: (order: Order) => void }) {
       name: formData.get("name") as string,
       email: formData.get("email") as string,
       phone: formData.get("phone") as string,
-      count,
+      event_id: event.id,
+      tickets: Object.entries(counts)
+        .filter(([, count]) => count > 0)
+        .map(([ticket_type_id, count]) => ({ ticket_type_id, count })),
     })
       .then(props.onCreate)
       .catch((e) => {
@@ -97,16 +98,39 @@ export default function OrderForm(props: { onCreate: (order: Order) => void }) {
     <form className="space-y-4" onSubmit={onSubmit}>
       <section>
         <h1 className="mb-2 text-center text-4xl font-bold tracking-tight">
-          STHLM VISION
+          {event?.name ?? "-"}
         </h1>
-        <p className="text-center text-gray-700">
-          Fryshuset 10 februari kl. 18:30
-        </p>
+        {event && (
+          <p className="text-center text-gray-700">
+            {event.venue}{" "}
+            {new Date(event.starts_at).toLocaleString("sv-SE", {
+              day: "numeric",
+              month: "long",
+              hour: "2-digit",
+              minute: "2-digit",
+            })}
+          </p>
+        )}
         <p className="mt-2 text-center text-gray-700">
-          {ticketsRemaining ?? "-"} biljetter kvar
+          {remaining?.total ?? "-"} biljetter kvar
         </p>
       </section>
-      <QuantityField value={count} onChange={setCount} price={price} />
+      <div className="space-y-2">
+        {ticketTypes.map((ticketType) => (
+          <QuantityField
+            key={ticketType.id}
+            ticketType={ticketType}
+            value={counts[ticketType.id] ?? 0}
+            onChange={(value) =>
+              setCounts((counts) => ({ ...counts, [ticketType.id]: value }))
+            }
+            remaining={remaining?.ticket_types[ticketType.id]}
+          />
+        ))}
+        <p className="text-xs text-gray-500">
+          I biljettpriset ingår garderob.
+        </p>
+      </div>
       <hr />
       <Field
         name="name"
@@ -131,7 +155,7 @@ export default function OrderForm(props: { onCreate: (order: Order) => void }) {
         <p className="rounded-lg bg-red-50 p-2 text-red-500">{error}</p>
       )}
       <button
-        disabled={creating || ticketsRemaining === 0}
+        disabled={creating || !event || count === 0 || remaining?.total === 0}
         className="rounded-lg bg-black px-4 py-3 text-sm font-medium text-white"
       >
         Betala ({total}&nbsp;kr) -&gt;
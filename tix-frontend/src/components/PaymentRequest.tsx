@@ -1,4 +1,4 @@
-import { Order, cancelOrder, completeOrder } from "@/lib/api";
+import { Order, cancelOrder, checkOutOrder } from "@/lib/api";
 import SwishQr, { SwishQrProps } from "./SwishQr";
 
 export interface PaymentRequestProps {
@@ -13,7 +13,7 @@ export default function PaymentRequest({
 }: PaymentRequestProps) {
   function onComplete() {
     if (confirm("Är du säker på att du har betalat?")) {
-      completeOrder(order.id, order.email)
+      checkOutOrder(order.id, order.email)
         .then(props.onComplete)
         .catch(() => {
           alert("Något gick fel. Försök igen.");
@@ -1,10 +1,16 @@
 const API_URL = process.env.NEXT_PUBLIC_API_URL || "http://localhost:8000";
 
+export interface TicketRequest {
+  ticket_type_id: string;
+  count: number;
+}
+
 export interface CreateOrder {
   email: string;
   name: string;
   phone: string;
-  count: number;
+  event_id: string;
+  tickets: TicketRequest[];
 }
 
 export interface Order {
@@ -15,9 +21,10 @@ export interface Order {
   amount: number;
   created_at: string;
   paid_at: string | null;
-  completed_at: string | null;
+  checked_out_at: string | null;
   canceled_at: string | null;
   emailed_at: string | null;
+  event_id: string;
 }
 
 export async function createOrder(req: CreateOrder): Promise<Order> {
@@ -53,16 +60,16 @@ export async function cancelOrder(id: string, email: string): Promise<Order> {
   return res.json();
 }
 
-export async function completeOrder(id: string, email: string): Promise<Order> {
+export async function checkOutOrder(id: string, email: string): Promise<Order> {
   const res = await request(
-    `/orders/${id}/complete?email=${encodeURIComponent(email)}`,
+    `/orders/${id}/checkout?email=${encodeURIComponent(email)}`,
     {
       method: "POST",
     },
   );
 
   if (!res.ok) {
-    throw new Error("Failed to complete order");
+    throw new Error("Failed to check out order");
   }
 
   return res.json();
@@ -144,8 +151,61 @@ export async function getOrder(
   return res.json();
 }
 
-export async function getTicketsRemaining(): Promise<number> {
-  const res = await request("/tickets/remaining");
+export interface Event {
+  id: string;
+  name: string;
+  venue: string;
+  starts_at: string;
+  capacity: number;
+  sales_start: string | null;
+  sales_end: string | null;
+}
+
+export interface TicketType {
+  id: string;
+  event_id: string;
+  name: string;
+  price: number;
+  quota: number;
+  public: boolean;
+}
+
+export interface TicketsRemaining {
+  total: number;
+  ticket_types: Record<string, number>;
+}
+
+export interface DetailedEvent extends Event {
+  ticket_types: TicketType[];
+  tickets_remaining: TicketsRemaining;
+}
+
+export async function getEvents(): Promise<Event[]> {
+  const res = await request("/events");
+
+  if (!res.ok) {
+    throw new Error("Failed to get events");
+  }
+
+  return res.json();
+}
+
+export async function getEvent(id: string): Promise<DetailedEvent> {
+  const res = await request(`/events/${encodeURIComponent(id)}`);
+
+  if (!res.ok) {
+    throw new Error("Failed to get event");
+  }
+
+  return res.json();
+}
+
+export async function getTicketsRemaining(
+  eventId: string,
+): Promise<TicketsRemaining> {
+  const res = await request(
+    `/events/${encodeURIComponent(eventId)}/remaining`,
+  );
 
   if (!res.ok) {
     throw new Error("Failed to get tickets remaining");
@@ -1,7 +1,17 @@
 import { useQuery } from "@tanstack/react-query";
 import { OrderDetails, useOrderStore } from "./state";
 import { useEffect } from "react";
-import { getOrder, getOrders, getTicketStats, getTickets } from "./api";
+import {
+  getEvent,
+  getEvents,
+  getOrder,
+  getOrders,
+  getTicketStats,
+  getTickets,
+  getTicketsRemaining,
+} from "./api";
+
+const EVENT_ID = process.env.NEXT_PUBLIC_EVENT_ID;
 
 export function useTickets(id?: string, email?: string) {
   return useQuery({
@@ -45,3 +55,37 @@ export const useTicketStats = () =>
     queryFn: getTicketStats,
     refetchInterval: 10000,
   });
+
+/** The event tickets are sold for: `NEXT_PUBLIC_EVENT_ID`, or else the next
+ * event that is still on sale. */
+export function useCurrentEvent() {
+  const { data: eventId } = useQuery({
+    queryKey: ["events", "current"],
+    enabled: !EVENT_ID,
+    queryFn: async () => {
+      const now = new Date();
+      const events = await getEvents();
+      const event = events.find(
+        (e) =>
+          new Date(e.starts_at) > now &&
+          (!e.sales_end || new Date(e.sales_end) > now),
+      );
+      return event?.id ?? null;
+    },
+  });
+  const id = EVENT_ID ?? eventId;
+
+  return useQuery({
+    queryKey: ["events", id],
+    enabled: !!id,
+    queryFn: () => getEvent(id!),
+  });
+}
+
+export const useTicketsRemaining = (eventId?: string) =>
+  useQuery({
+    queryKey: ["events", eventId, "remaining"],
+    enabled: !!eventId,
+    queryFn: () => getTicketsRemaining(eventId!),
+    refetchInterval: 10000,
+  });